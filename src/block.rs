@@ -1,5 +1,7 @@
+use std::f64::consts::PI;
 use crate::nodes::BBox;
 use crate::cluster::{ClusterNode, ClusterTree};
+use crate::kernels::Kernel;
 
 
 // comments:
@@ -8,6 +10,7 @@ use crate::cluster::{ClusterNode, ClusterTree};
 // then again matrix methods might require the full tree, either way i like it, let's keep it moving
 
 // categorise as near for full resolution and far for approximation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockType {
     Near, // dense
     Far, // approximation
@@ -17,6 +20,7 @@ pub enum BlockType {
 // can the logic be D free / rely only on impl logic further up the line
 
  // source and target will later translate to column and row 
+#[derive(Clone)]
 pub struct BlockNode{
     pub target_index: usize, // index of Cluster node in target Ctree
     pub source_index: usize, // index of Cluster node in source Ctree
@@ -25,6 +29,7 @@ pub struct BlockNode{
     pub block_type: BlockType // assigned to leaf blocks 
 }
 
+#[derive(Clone)]
 pub struct BlockTree {
     pub nodes: Vec<BlockNode>,
     pub root_id: usize
@@ -36,10 +41,27 @@ pub fn is_far<const D: usize>(source_bbox: &BBox<D>, target_bbox: &BBox<D>, max_
     dist > max_dist // True if too far for full resolution
 }
 
+// wavenumber aware version of the above, k = oscillation + i decay
+// - oscillation: ACA rank grows with the number of wavelengths across the pair, so ask for centre distance
+//   > k diam^2 / 2pi too (parabolic admissibility) -- with k = 0 this is just is_far
+// - decay: once e^{-Im k gap} is below tol (the one assembly will use) the whole block is negligible so it is far
+//   whatever its size, assembly then stores it as rank 0 without touching the kernel
+pub fn is_far_wave<const D: usize>(source_bbox: &BBox<D>, target_bbox: &BBox<D>, max_dist: f64, oscillation: f64, decay: f64, tol: f64) -> bool {
+    if decay > 0.0 && is_negligible(source_bbox, target_bbox, decay, tol) { return true }
+    let diam: f64 = source_bbox.diameter().max(target_bbox.diameter());
+    let dist: f64 = BBox::bbox_distance(source_bbox, target_bbox);
+    dist > max_dist.max(oscillation * diam * diam / (2.0 * PI))
+}
+
+// every entry of the block is below tol relative to the near field
+pub fn is_negligible<const D: usize>(source_bbox: &BBox<D>, target_bbox: &BBox<D>, decay: f64, tol: f64) -> bool {
+    decay > 0.0 && decay * BBox::gap(source_bbox, target_bbox) > (1.0 / tol).ln()
+}
+
 impl BlockTree {
     
     fn build_blocks<const D: usize>( &mut self, target_index: usize, source_index: usize, 
-        target_tree: &ClusterTree<D>, source_tree: &ClusterTree<D>, max_dist: f64) -> usize {
+        target_tree: &ClusterTree<D>, source_tree: &ClusterTree<D>, far: &dyn Fn(&BBox<D>, &BBox<D>) -> bool) -> usize {
 
             // find corresponding ClusterNode and associated bboxes
             let target_cluster: &ClusterNode<D> = &target_tree.nodes[target_index];
@@ -49,7 +71,7 @@ impl BlockTree {
             let source_bbox: &BBox<D> = &source_cluster.bbox;
 
             // proximity check
-            let too_far: bool = is_far(source_bbox, target_bbox, max_dist);
+            let too_far: bool = far(source_bbox, target_bbox);
 
             // check if blocks are leaves 
             let target_is_leaf: bool = target_cluster.children.is_none();
@@ -71,7 +93,7 @@ impl BlockTree {
             if target_is_leaf && source_is_leaf {
                 let root_id: usize = self.nodes.len();
                 self.nodes.push(BlockNode {target_index, source_index, children: None, block_type: BlockType::Near});
-                return root_id
+                root_id
             }
 
             // recursive block building yay
@@ -86,7 +108,7 @@ impl BlockTree {
                     (Some([tc0, tc1]), Some([sc0, sc1])) => {
                         for &tci in &[tc0, tc1] {
                             for &sci in &[sc0, sc1] {
-                                let child: usize = self.build_blocks(tci, sci, target_tree, source_tree, max_dist);
+                                let child: usize = self.build_blocks(tci, sci, target_tree, source_tree, far);
                                 child_indices.push(child);
                             }
                         }
@@ -95,7 +117,7 @@ impl BlockTree {
                     // the source cluster is a leaf
                     (Some([tc0, tc1]), None) => {
                         for &tci in &[tc0, tc1] {
-                            let child = self.build_blocks(tci, source_index, target_tree, source_tree, max_dist);
+                            let child = self.build_blocks(tci, source_index, target_tree, source_tree, far);
                             child_indices.push(child);
                         }
                     }
@@ -103,7 +125,7 @@ impl BlockTree {
                     // the target cluster is a leaf
                     (None, Some([s0, s1])) => {
                         for &sci in &[s0, s1] {
-                            let child = self.build_blocks(target_index, sci, target_tree, source_tree, max_dist);
+                            let child = self.build_blocks(target_index, sci, target_tree, source_tree, far);
                             child_indices.push(child);
                         }
                     }
@@ -129,12 +151,46 @@ impl BlockTree {
             let target_index: usize = target_tree.root_id;
             let source_index: usize = source_tree.root_id;
 
-            let root_id: usize = tree.build_blocks(target_index, source_index, target_tree, source_tree, max_dist);
+            let root_id: usize = tree.build_blocks(target_index, source_index, target_tree, source_tree,
+                &|source_bbox, target_bbox| is_far(source_bbox, target_bbox, max_dist));
+
+            tree.root_id = root_id;
+            tree
+        }
 
-                
-            print!("blocktree succesfully built");
+        // as build_tree but admissibility picks up the kernel's oscillation and decay, see is_far_wave. tol should match
+        // the assembly's (ACA_TOL for plain assemble) so blocks dropped here are the ones assembly would call negligible
+        // eg. BlockTree::build_tree_for_kernel(&tree, &tree, 1.0, &Helmholtz::new(Complex64::new(20.0, 2.0)), ACA_TOL)
+        pub fn build_tree_for_kernel<const D: usize, K: Kernel<D>>(target_tree: &ClusterTree<D>, source_tree: &ClusterTree<D>,
+            max_dist: f64, kernel: &K, tol: f64) -> Self {
+
+            let mut tree: BlockTree = BlockTree { nodes: Vec::new(), root_id: 0 };
+            let (oscillation, decay): (f64, f64) = (kernel.oscillation(), kernel.decay_rate());
+
+            let root_id: usize = tree.build_blocks(target_tree.root_id, source_tree.root_id, target_tree, source_tree,
+                &|source_bbox, target_bbox| is_far_wave(source_bbox, target_bbox, max_dist, oscillation, decay, tol));
 
             tree.root_id = root_id;
             tree
         }
+
+        // depth of every BlockNode below the root, indexed like .nodes
+        pub fn depths(&self) -> Vec<usize> {
+            let mut depths: Vec<usize> = vec![0; self.nodes.len()];
+            let mut stack: Vec<usize> = vec![self.root_id];
+            while let Some(id) = stack.pop() {
+                if let Some(children) = &self.nodes[id].children {
+                    for &child in children {
+                        depths[child] = depths[id] + 1;
+                        stack.push(child);
+                    }
+                }
+            }
+            depths
+        }
+
+        // ids of the leaf BlockNodes, in the same order HMatrix.blocks is filled
+        pub fn leaf_ids(&self) -> Vec<usize> {
+            (0..self.nodes.len()).filter(|&id| self.nodes[id].children.is_none()).collect()
+        }
 }
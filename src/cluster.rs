@@ -2,30 +2,41 @@ use crate::nodes::{Nodes, BBox};
 
 // individual node of the tree containing indices of a fraction of the total nodes
 // relationships between nodes to be kept with each node
+#[derive(Clone)]
 pub struct ClusterNode<const D: usize> {
     pub bbox: BBox<D>,
     pub indices: Vec<usize>,  // indices into Nodes.points
     pub children: Option<[usize; 2]>, // indices into ClusterTree.nodes or None
     pub level: u32, // how many splits have we had to this box? 0 = root bbox
+    pub offset: usize, // where indices start in leaf order, see ClusterTree.positions
 }
 
+#[derive(Clone)]
 pub struct ClusterTree<const D: usize> {
     pub nodes: Vec<ClusterNode<D>>,
-    pub root_id: usize // index of root ClusterNode (len(.nodes)-1)
+    pub root_id: usize, // index of root ClusterNode (len(.nodes)-1)
+
+    // reading the leaves left to right every cluster is one contiguous run of points,
+    // positions[i] is where point i lands in that order so membership is just a range check
+    pub positions: Vec<usize>,
+
+    // inverse of positions, the points in leaf order -- see leaf_order_indices
+    leaf_order: Vec<usize>,
 }
 
 impl<const D: usize> ClusterTree<D> {
 
     // recursive ClusterNode builder from indices into Nodes
-    fn build_nodes(&mut self, nodes: &Nodes<D>, mut indices: Vec<usize>, level: u32, leaf_size: usize) -> usize { 
+    fn build_nodes(&mut self, nodes: &Nodes<D>, mut indices: Vec<usize>, level: u32, leaf_size: usize, offset: usize) -> usize { 
 
         // create a bounding box of given indices 
         let bbox: BBox<D> = nodes.bbox_from_indices(&indices); // will break if indices empty, see Nodes impl
 
         // check if alrady contains max number of points  -> terminate branch (== leaf node)
         if indices.len() <= leaf_size {
+            for (k, &i) in indices.iter().enumerate() { self.positions[i] = offset + k; }
             let root_id: usize = self.nodes.len();
-            self.nodes.push( ClusterNode{ bbox, indices, children: None, level});
+            self.nodes.push( ClusterNode{ bbox, indices, children: None, level, offset});
             return root_id;
         }
 
@@ -53,30 +64,52 @@ impl<const D: usize> ClusterTree<D> {
         let right_indices: Vec<usize> = indices[mid..].to_vec();
 
         // recursion to build children bboxes, from left and right indices
-        let left_id: usize = self.build_nodes(nodes, left_indices, level + 1, leaf_size);
-        let right_id: usize = self.build_nodes(nodes, right_indices, level + 1, leaf_size);
+        let left_id: usize = self.build_nodes(nodes, left_indices, level + 1, leaf_size, offset);
+        let right_id: usize = self.build_nodes(nodes, right_indices, level + 1, leaf_size, offset + mid);
         // spacetime scrunch
 
         // return total number of produced nodes - 1 ie. index of root ClusterNode
         let root_id: usize = self.nodes.len(); // taken before last push so can be used as index
 
         // add this ClusterNode to ClusterTree, children ClusterNodes also added recursively
-        self.nodes.push(ClusterNode { bbox, indices: indices, children: Some([left_id, right_id]), level});
+        self.nodes.push(ClusterNode { bbox, indices, children: Some([left_id, right_id]), level, offset});
 
         root_id
     }
 
     pub fn build_tree(nodes: &Nodes<D>, leaf_size: usize) -> Self {
 
-        let mut tree: ClusterTree<D> = ClusterTree { nodes: Vec::new(), root_id:0};
+        let mut tree: ClusterTree<D> = ClusterTree { nodes: Vec::new(), root_id:0, positions: vec![0; nodes.points.len()], leaf_order: Vec::new()};
 
         // for full tree, simply run build_nodes with all Node indices
         let all_indices: Vec<usize> = (0..nodes.points.len()).collect();
-        let root_id: usize = tree.build_nodes(nodes, all_indices, 0, leaf_size);
+        let root_id: usize = tree.build_nodes(nodes, all_indices, 0, leaf_size, 0);
         tree.root_id = root_id;
+
+        tree.leaf_order = vec![0; tree.positions.len()];
+        for (i, &pos) in tree.positions.iter().enumerate() { tree.leaf_order[pos] = i; }
         tree
     }
 
+    // node_id's points in leaf order: the same set as its indices (which parents keep sorted along their own split dim),
+    // ordered so local_index lines up -- what HMatrix blocks use for their rows / cols
+    pub fn leaf_order_indices(&self, node_id: usize) -> &[usize] {
+        let node: &ClusterNode<D> = &self.nodes[node_id];
+        &self.leaf_order[node.offset..node.offset + node.indices.len()]
+    }
+
+    // is point i (index into Nodes.points) inside ClusterNode node_id, O(1)
+    pub fn contains(&self, node_id: usize, i: usize) -> bool {
+        let node: &ClusterNode<D> = &self.nodes[node_id];
+        let pos: usize = self.positions[i];
+        pos >= node.offset && pos < node.offset + node.indices.len()
+    }
+
+    // where point i sits in node_id's indices, assumes contains(node_id, i)
+    pub fn local_index(&self, node_id: usize, i: usize) -> usize {
+        self.positions[i] - self.nodes[node_id].offset
+    }
+
     // tree build printer
     pub fn print(&self) {
         self.print_node(self.root_id, 0);
@@ -107,7 +140,8 @@ impl<const D: usize> ClusterTree<D> {
 #[cfg(test)] 
 mod cluster_tree_tests { 
 
-    // use super::*; 
+    use super::*; 
+    use crate::functions::cardioid_nodes;
 
     // separate tests for each input error? 
     // think of things which could go wrong?
@@ -119,6 +153,25 @@ mod cluster_tree_tests {
         // check that leaf being too big or too small breaks the function correctly
     }
 
+    #[test]
+    fn contains_matches_indices() {
+        let nodes: Nodes<2> = Nodes::new(cardioid_nodes(57));
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 5);
+        for (id, node) in tree.nodes.iter().enumerate() {
+            for i in 0..57 {
+                assert_eq!(tree.contains(id, i), node.indices.contains(&i));
+            }
+            let mut ordered: Vec<usize> = tree.leaf_order_indices(id).to_vec();
+            for (k, &i) in ordered.iter().enumerate() {
+                assert_eq!(tree.local_index(id, i), k);
+            }
+            ordered.sort();
+            let mut indices: Vec<usize> = node.indices.clone();
+            indices.sort();
+            assert_eq!(ordered, indices);
+        }
+    }
+
     // bbox from indices function checks for things being too small
     // should this be a test here ? 
 }
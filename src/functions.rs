@@ -1,7 +1,9 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use crate::kernels::Kernel;
 use crate::nodes::Nodes;
-use crate::cluster::{ClusterTree, ClusterNode};
-use crate::block::{BlockTree, BlockType};
+use crate::hmatrix::HMatrix;
+use crate::scalar::Scalar;
 
 // place for main callables which just take in nodes, kernels, etc
 
@@ -28,4 +30,171 @@ pub fn cardioid_nodes(n: usize) -> Vec<[f64; 2]> {
         pts.push([x, y]);
     }
     pts
-}
\ No newline at end of file
+}
+
+// cardioid_nodes plus outward unit normals and trapezoidal arclength weights |gamma'(theta)| 2pi/n
+// the cusp at theta = 0 gets weight 0 and an arbitrary normal since gamma' vanishes there
+pub fn cardioid_boundary(n: usize) -> Nodes<2> {
+    let points: Vec<[f64; 2]> = cardioid_nodes(n);
+    let mut weights: Vec<f64> = Vec::with_capacity(n);
+    let mut normals: Vec<[f64; 2]> = Vec::with_capacity(n);
+    let mut curvatures: Vec<f64> = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let theta: f64 = 2.0 * PI * (i as f64) / (n as f64);
+        let r: f64 = 1.0 - theta.cos();
+        let dr: f64 = theta.sin();
+        let dx: f64 = dr * theta.cos() - r * theta.sin();
+        let dy: f64 = dr * theta.sin() + r * theta.cos();
+        let speed: f64 = (dx * dx + dy * dy).sqrt();
+
+        weights.push(speed * 2.0 * PI / (n as f64));
+        // counter clockwise so outward is the tangent turned clockwise
+        if speed > 0.0 { normals.push([dy / speed, -dx / speed]) } else { normals.push([1.0, 0.0]) }
+        // (r^2 + 2r'^2 - r r'') / (r^2 + r'^2)^{3/2} collapses to 3 / (2 |x'|), infinite at the cusp (weight 0 there)
+        if speed > 0.0 { curvatures.push(1.5 / speed) } else { curvatures.push(0.0) }
+    }
+    Nodes::with_boundary(points, weights, normals).set_curvatures(curvatures)
+}
+
+// exact n_targets x n_sources kernel matrix, row major like DenseBlock -- the thing the HMatrix is pretending to be
+// (times components on both sides for tensor kernels)
+pub fn dense_matrix<const D: usize, K: Kernel<D>>(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>, kernel: &K) -> Vec<K::Scalar> {
+    let c: usize = kernel.components();
+    let mut data: Vec<K::Scalar> = Vec::with_capacity(target_nodes.points.len() * source_nodes.points.len() * c * c);
+    for i in 0..target_nodes.len() * c {
+        for j in 0..source_nodes.len() * c {
+            data.push(kernel.eval_nodes(target_nodes, i, source_nodes, j));
+        }
+    }
+    data
+}
+
+// relative errors of an HMatrix against the exact kernel matrix
+#[derive(Debug, Clone, Copy)]
+pub struct ApproximationError {
+    pub frobenius: f64, // ||A - H||_F / ||A||_F
+    pub spectral: f64, // ||A - H||_2 / ||A||_2, estimated by power iteration from a random start
+}
+
+// builds both matrices densely so keep it to small problems
+pub fn approximation_error<const D: usize, K: Kernel<D>>(hmatrix: &HMatrix<D, K>,
+    target_nodes: &Nodes<D>, source_nodes: &Nodes<D>, power_iterations: usize) -> ApproximationError {
+
+    let exact: Vec<K::Scalar> = dense_matrix(target_nodes, source_nodes, &hmatrix.kernel);
+    let approx: Vec<K::Scalar> = hmatrix.to_dense();
+    let diff: Vec<K::Scalar> = exact.iter().zip(&approx).map(|(&a, &h)| a - h).collect();
+
+    let frob = |m: &[K::Scalar]| m.iter().map(|z| z.abs_sqr()).sum::<f64>().sqrt();
+    let exact_frob: f64 = frob(&exact);
+    let frobenius: f64 = if exact_frob > 0.0 { frob(&diff) / exact_frob } else { frob(&diff) };
+
+    let (n_rows, n_cols) = (hmatrix.n_rows, hmatrix.n_cols);
+    let exact_2: f64 = spectral_norm_estimate(&exact, n_rows, n_cols, power_iterations);
+    let diff_2: f64 = spectral_norm_estimate(&diff, n_rows, n_cols, power_iterations);
+    let spectral: f64 = if exact_2 > 0.0 { diff_2 / exact_2 } else { diff_2 };
+
+    ApproximationError { frobenius, spectral }
+}
+
+// power iteration on A^H A for a row major m x n matrix, fixed seed so results are repeatable
+fn spectral_norm_estimate<T: Scalar>(a: &[T], m: usize, n: usize, iterations: usize) -> f64 {
+    let mut rng: StdRng = StdRng::seed_from_u64(0);
+    let mut x: Vec<T> = (0..n).map(|_| T::from_real(rng.gen_range(-1.0..1.0))).collect();
+    let mut sigma: f64 = 0.0;
+
+    for _ in 0..iterations.max(1) {
+        let x_norm: f64 = x.iter().map(|z| z.abs_sqr()).sum::<f64>().sqrt();
+        if x_norm == 0.0 { return 0.0 }
+        x.iter_mut().for_each(|z| *z /= T::from_real(x_norm));
+
+        // y = A x
+        let y: Vec<T> = (0..m).map(|i| (0..n).map(|j| a[i * n + j] * x[j]).sum()).collect();
+        sigma = y.iter().map(|z| z.abs_sqr()).sum::<f64>().sqrt();
+
+        // x = A^H y
+        x = (0..n).map(|j| (0..m).map(|i| a[i * n + j].conj() * y[i]).sum()).collect();
+    }
+    sigma
+}
+
+// test fixture shared across the crate: Laplace on n cardioid points, leaf size 8, max_dist 1, far field compressed to tol
+#[cfg(test)]
+pub(crate) fn cardioid_hmatrix(n: usize, tol: f64) -> (Nodes<2>, HMatrix<2, crate::kernels::Laplace>) {
+    use crate::cluster::ClusterTree;
+    use crate::block::BlockTree;
+
+    let nodes: Nodes<2> = cardioid_boundary(n); // weighted, so the diagonal is the Laplace self term
+    let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+    let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+    let hmat = HMatrix::assemble_with_tol(&nodes, &nodes, &tree, &tree, blocks, crate::kernels::Laplace, tol);
+    (nodes, hmat)
+}
+
+#[cfg(test)]
+mod approximation_tests {
+    use super::*;
+    use crate::kernels::{Laplace, HelmholtzNormal};
+    use crate::cluster::ClusterTree;
+    use crate::block::BlockTree;
+    use crate::hmatrix::BlockStorage;
+
+    #[test]
+    fn cardioid_boundary_length_and_normals() {
+        let nodes: Nodes<2> = cardioid_boundary(400);
+        let length: f64 = nodes.weights.as_ref().unwrap().iter().sum();
+        assert!((length - 8.0).abs() < 1e-4); // perimeter of r = 1 - cos(theta), only O(h^2) because of the cusp
+
+        // theta = pi is the far left of the curve, normal should point straight out along -x
+        let normal: &[f64; 2] = nodes.normal(200).unwrap();
+        assert!((normal[0] + 1.0).abs() < 1e-12 && normal[1].abs() < 1e-12);
+    }
+
+    #[test]
+    fn to_dense_matches_kernel() {
+        let (nodes, hmat) = cardioid_hmatrix(120, 1e-10);
+        assert!(hmat.blocks.iter().any(|b| matches!(b, BlockStorage::LowRank(_))));
+
+        let err: ApproximationError = approximation_error(&hmat, &nodes, &nodes, 30);
+        assert!(err.frobenius < 1e-8, "{:?}", err);
+        assert!(err.spectral < 1e-8, "{:?}", err);
+    }
+
+    #[test]
+    fn double_layer_assembles_with_normals() {
+        // fibonacci points on the unit sphere, normals are the points themselves
+        let n: usize = 150;
+        let golden: f64 = PI * (3.0 - 5.0_f64.sqrt());
+        let points: Vec<[f64; 3]> = (0..n).map(|i| {
+            let z: f64 = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+            let rho: f64 = (1.0 - z * z).sqrt();
+            [rho * (golden * i as f64).cos(), rho * (golden * i as f64).sin(), z]
+        }).collect();
+        let nodes: Nodes<3> = Nodes::with_normals(points.clone(), points);
+        let tree: ClusterTree<3> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, HelmholtzNormal::new(2.0));
+        let err: ApproximationError = approximation_error(&hmat, &nodes, &nodes, 20);
+        assert!(err.frobenius < 1e-6, "{:?}", err);
+    }
+
+    #[test]
+    fn looser_tol_gives_larger_error() {
+        let (nodes, tight) = cardioid_hmatrix(120, 1e-10);
+        let (_, loose) = cardioid_hmatrix(120, 1e-2);
+        let tight_err: ApproximationError = approximation_error(&tight, &nodes, &nodes, 30);
+        let loose_err: ApproximationError = approximation_error(&loose, &nodes, &nodes, 30);
+        assert!(loose_err.frobenius > tight_err.frobenius);
+    }
+
+    #[test]
+    fn cardioid_single_layer_converges() {
+        // S[1] at theta = pi with the log self terms on the diagonal, halving h should barely move it
+        let single_layer = |n: usize| -> f64 {
+            let nodes: Nodes<2> = cardioid_boundary(n);
+            let row: Vec<f64> = dense_matrix(&nodes, &nodes, &Laplace)[n / 2 * n..(n / 2 + 1) * n].to_vec();
+            row.iter().enumerate().map(|(j, a)| a * nodes.weight(j)).sum()
+        };
+        assert!((single_layer(128) - single_layer(256)).abs() < 1e-4);
+    }
+}
@@ -1,8 +1,15 @@
-use num_complex::Complex64;
-use crate::kernels::Kernel;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::kernels::{Kernel, KernelDk, Dk};
 use crate::nodes::Nodes;
 use crate::cluster::{ClusterTree, ClusterNode};
-use crate::block::{BlockTree, BlockType};
+use crate::block::{BlockTree, BlockType, is_negligible};
+use crate::scalar::Scalar;
+
+// default relative tolerance for ACA, stops once the next rank one update is this small compared to the block so far
+pub const ACA_TOL: f64 = 1e-8;
 
 
 // turning BlockTree into that sweet sweet Hmatrix 
@@ -10,33 +17,131 @@ use crate::block::{BlockTree, BlockType};
 
 
 // aptly named storage method for blocks
-pub enum BlockStorage {
-    Dense(DenseBlock),
-    LowRank(LowRankBlock),
+pub enum BlockStorage<T: Scalar> {
+    Dense(DenseBlock<T>),
+    LowRank(LowRankBlock<T>),
 } // dense and lowrank for sorting during Hmatrix construction
 
 // high resolution block
-pub struct DenseBlock {
-    pub rows: Vec<usize>, // indices into target Nodes 
-    pub cols: Vec<usize>,  // indices into source Nodes
-    pub data: Vec<Complex64>, // going to store data in row major order ie. A00 -> A0n, A10 -> A1n, etc 
+pub struct DenseBlock<T: Scalar> {
+    pub rows: Vec<usize>, // unknowns on the target Nodes, node * components + component (so just the node for scalar kernels)
+    pub cols: Vec<usize>,  // unknowns on the source Nodes
+    pub data: Vec<T>, // going to store data in row major order ie. A00 -> A0n, A10 -> A1n, etc 
     // Aij = data[i * len[cols] + j]
 }
 
 // approximation using A = UV^T, where U and V are essentially basis vectors since you can approximate the full Aij as basically linearly dependent 
 // ACA algorithm needed in eval
-pub struct LowRankBlock {
-    pub rows: Vec<usize>, // indices into target Nodes 
-    pub cols: Vec<usize>, // indices into source Nodes 
+pub struct LowRankBlock<T: Scalar> {
+    pub rows: Vec<usize>, // unknowns on the target Nodes, as in DenseBlock
+    pub cols: Vec<usize>, // unknowns on the source Nodes
 
     // keep rank < min(rows, columns) == number of columns in U and V, how much resolution do you want to keep?
-    //pub rank: usize,
+    pub rank: usize,
+
+    pub u: Vec<T>, // len(rows) x rank matrix
+    pub v: Vec<T>, // len(col) x rank matrix 
+    // row major as above -- Aij = sum_k u[i * rank + k] * v[j * rank + k]
+
+    // entries overwritten after assembly (HMatrix::set, eg. quadrature corrections), local (i, j) -> A_ij - (UV^T)_ij
+    // kept sparse on top of UV^T so setting entries never grows the rank, ordered so apply sums them the same way every run
+    pub corrections: BTreeMap<(usize, usize), T>,
+}
 
-    //pub u: Vec<Complex64>, // len(rows) x rank matrix
-    //pub v: Vec<Complex64>, // len(col) x rank matrix 
-    // row major as above 
+impl<T: Scalar> DenseBlock<T> {
+
+    // entry (i, j) in local block numbering
+    pub fn entry(&self, i: usize, j: usize) -> T {
+        self.data[i * self.cols.len() + j]
+    }
+
+    // already stored densely, just here to match LowRankBlock
+    pub fn to_dense(&self) -> Vec<T> {
+        self.data.clone()
+    }
 }
 
+impl<T: Scalar> LowRankBlock<T> {
+
+    // entry (i, j) in local block numbering, O(rank)
+    pub fn entry(&self, i: usize, j: usize) -> T {
+        let ui: &[T] = &self.u[i * self.rank..(i + 1) * self.rank];
+        let vj: &[T] = &self.v[j * self.rank..(j + 1) * self.rank];
+        let low_rank: T = ui.iter().zip(vj).map(|(&a, &b)| a * b).sum();
+        match self.corrections.get(&(i, j)) { Some(&delta) => low_rank + delta, None => low_rank }
+    }
+
+    // make entry (i, j) equal value through the sparse corrections, replacing any earlier one there
+    pub fn set(&mut self, i: usize, j: usize, value: T) {
+        self.corrections.remove(&(i, j));
+        let delta: T = value - self.entry(i, j);
+        if delta != T::zero() { self.corrections.insert((i, j), delta); }
+    }
+
+    // expand UV^T into a len(rows) x len(cols) row major block
+    pub fn to_dense(&self) -> Vec<T> {
+        let m: usize = self.rows.len();
+        let n: usize = self.cols.len();
+        let mut data: Vec<T> = Vec::with_capacity(m * n);
+        for i in 0..m {
+            for j in 0..n {
+                data.push(self.entry(i, j));
+            }
+        }
+        data
+    }
+
+    // A += a b^T, just widens U and V by one column
+    pub fn add_rank_one(&mut self, a: &[T], b: &[T]) {
+        assert!(a.len() == self.rows.len() && b.len() == self.cols.len());
+        let k: usize = self.rank;
+        let widen = |factor: &[T], extra: &[T]| -> Vec<T> {
+            let mut wider: Vec<T> = Vec::with_capacity(extra.len() * (k + 1));
+            for (i, &new) in extra.iter().enumerate() {
+                wider.extend_from_slice(&factor[i * k..(i + 1) * k]);
+                wider.push(new);
+            }
+            wider
+        };
+        self.u = widen(&self.u, a);
+        self.v = widen(&self.v, b);
+        self.rank += 1;
+    }
+}
+
+impl<T: Scalar> BlockStorage<T> {
+    pub fn rows(&self) -> &[usize] {
+        match self { BlockStorage::Dense(b) => &b.rows, BlockStorage::LowRank(b) => &b.rows }
+    }
+
+    pub fn cols(&self) -> &[usize] {
+        match self { BlockStorage::Dense(b) => &b.cols, BlockStorage::LowRank(b) => &b.cols }
+    }
+
+    pub fn entry(&self, i: usize, j: usize) -> T {
+        match self { BlockStorage::Dense(b) => b.entry(i, j), BlockStorage::LowRank(b) => b.entry(i, j) }
+    }
+
+    pub fn to_dense(&self) -> Vec<T> {
+        match self { BlockStorage::Dense(b) => b.to_dense(), BlockStorage::LowRank(b) => b.to_dense() }
+    }
+}
+
+
+// how well one leaf of the HMatrix matches the kernel it came from
+#[derive(Debug, Clone, Copy)]
+pub struct BlockError {
+    pub block_id: usize, // index into BlockTree.nodes
+    pub target_index: usize, // ClusterNode in target tree
+    pub source_index: usize, // ClusterNode in source tree
+    pub depth: usize, // how far below the BlockTree root
+    pub block_type: BlockType,
+    pub rank: Option<usize>, // None for dense blocks
+    pub n_rows: usize,
+    pub n_cols: usize,
+    pub rel_error: f64, // ||A_b - H_b|| / ||A_b|| over the checked entries
+    pub sampled: bool, // false if every entry was checked
+}
 
 // wamt hmatrix to look something like this 
 pub struct HMatrix<const D: usize, K: Kernel<D>> {
@@ -45,17 +150,27 @@ pub struct HMatrix<const D: usize, K: Kernel<D>> {
     pub block_tree: BlockTree,
 
     // actual Blocks 
-    pub blocks: Vec<BlockStorage>,
+    pub blocks: Vec<BlockStorage<K::Scalar>>,
 
     // kernel we want to use 
     pub kernel: K,
 
     // distance for near/far taken into account in Blocktree
     // nodes and stuff will be important but don't need to be stored here
+    // cluster trees are kept though, entry lookup walks them alongside the BlockTree. shared rather than copied, so
+    // matrices on the same trees (assemble_dk, or the caller's own through assemble_shared) all point at one set
+    pub target_tree: Arc<ClusterTree<D>>,
+    pub source_tree: Arc<ClusterTree<D>>,
+
+    // BlockTree.nodes id of each entry in blocks, ascending
+    pub leaf_ids: Vec<usize>,
 
     // dimensions of the matrix?
     pub n_rows: usize,
     pub n_cols: usize,
+
+    // set by KapurRokhlin::correct, which scales entries in place and so must only ever run once on a matrix
+    pub quadrature_corrected: bool,
 }
 
 // Dense and ACA block construction functions -- in hmatrix impl
@@ -78,13 +193,33 @@ impl<const D: usize, K: Kernel<D>> HMatrix<D, K> {
     pub fn assemble(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
         target_tree: &ClusterTree<D>, source_tree: &ClusterTree<D>,
         block_tree: BlockTree, kernel: K) -> Self {
+            Self::assemble_with_tol(target_nodes, source_nodes, target_tree, source_tree, block_tree, kernel, ACA_TOL)
+    }
+
+    // as above but with control over how hard the far field gets compressed
+    // the trees are copied into the matrix, once if target and source are the same tree
+    pub fn assemble_with_tol(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
+        target_tree: &ClusterTree<D>, source_tree: &ClusterTree<D>,
+        block_tree: BlockTree, kernel: K, tol: f64) -> Self {
+            let target: Arc<ClusterTree<D>> = Arc::new(target_tree.clone());
+            let source: Arc<ClusterTree<D>> = if std::ptr::eq(target_tree, source_tree) { Arc::clone(&target) } else { Arc::new(source_tree.clone()) };
+            Self::assemble_shared(target_nodes, source_nodes, target, source, block_tree, kernel, tol)
+    }
+
+    // as assemble_with_tol on trees that are already shared, nothing is copied -- what assemble_dk uses, and the way to
+    // put several matrices on one set of trees
+    pub fn assemble_shared(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
+        target_tree: Arc<ClusterTree<D>>, source_tree: Arc<ClusterTree<D>>,
+        block_tree: BlockTree, kernel: K, tol: f64) -> Self {
 
             // rows and columns from nodes
-            let n_rows: usize = target_nodes.points.len();
-            let n_cols: usize = source_nodes.points.len();
+            // tensor kernels own several unknowns per node, each cluster expands into block rows / cols of them
+            let c: usize = kernel.components();
+            let n_rows: usize = target_nodes.points.len() * c;
+            let n_cols: usize = source_nodes.points.len() * c;
 
             // set up blocks
-            let mut blocks: Vec<BlockStorage> = Vec::new(); // can this be preallocated
+            let mut blocks: Vec<BlockStorage<K::Scalar>> = Vec::new(); // can this be preallocated
 
             // filter through the block tree for leaves, pontificating on this found in block.rs
             // thanks compsudoku
@@ -93,49 +228,480 @@ impl<const D: usize, K: Kernel<D>> HMatrix<D, K> {
                 // extract global node indices from block associated clusternodes
                 let target_node: &ClusterNode<D> = &target_tree.nodes[block.target_index];
                 let source_node: &ClusterNode<D> = &source_tree.nodes[block.source_index];
-                let rows: Vec<usize> = target_node.indices.clone();
-                let cols: Vec<usize> = source_node.indices.clone();
+                let rows: Vec<usize> = expand(target_tree.leaf_order_indices(block.target_index), c);
+                let cols: Vec<usize> = expand(source_tree.leaf_order_indices(block.source_index), c);
 
                 // sort into resolution function based off of block type 
-                let stored_block: BlockStorage = match block.block_type {
+                let stored_block: BlockStorage<K::Scalar> = match block.block_type {
                     BlockType::Near => {
-                        let dense: DenseBlock = Self::build_dense_block(target_nodes, source_nodes, rows, cols, &kernel);
+                        let dense: DenseBlock<K::Scalar> = Self::build_dense_block(target_nodes, source_nodes, rows, cols, &kernel);
                         BlockStorage::Dense(dense)
                     }
+                    // absorbing kernels (complex k): far enough apart the block is below tol everywhere, store it as rank 0
+                    BlockType::Far if is_negligible(&source_node.bbox, &target_node.bbox, kernel.decay_rate(), tol) => {
+                        BlockStorage::LowRank(LowRankBlock { rows, cols, rank: 0, u: Vec::new(), v: Vec::new(), corrections: BTreeMap::new() })
+                    }
                     BlockType::Far => {
-                        let lowrank: LowRankBlock = Self::build_LR_block(target_nodes, source_nodes, rows, cols, &kernel);
+                        let lowrank: LowRankBlock<K::Scalar> = Self::build_lr_block(target_nodes, source_nodes, rows, cols, &kernel, tol);
                         BlockStorage::LowRank(lowrank)
                     }
                 };
                 blocks.push(stored_block);
             }
 
-            Self { block_tree, blocks, kernel, n_rows, n_cols}
+            let leaf_ids: Vec<usize> = block_tree.leaf_ids();
+            Self { block_tree, blocks, kernel, target_tree, source_tree, leaf_ids, n_rows, n_cols, quadrature_corrected: false}
+    }
+
+    // dA/dk on the same BlockTree and cluster trees as this matrix, so the two line up block for block in Newton updates
+    pub fn assemble_dk(&self, target_nodes: &Nodes<D>, source_nodes: &Nodes<D>) -> HMatrix<D, Dk<K>> where K: KernelDk<D> + Clone {
+        HMatrix::assemble_shared(target_nodes, source_nodes, Arc::clone(&self.target_tree), Arc::clone(&self.source_tree),
+            self.block_tree.clone(), Dk::new(self.kernel.clone()), ACA_TOL)
     }
 
     pub fn build_dense_block(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
-        rows: Vec<usize>, cols: Vec<usize>, kernel: &K) -> DenseBlock {
+        rows: Vec<usize>, cols: Vec<usize>, kernel: &K) -> DenseBlock<K::Scalar> {
 
         // preallocation is a lovely thing
         let m: usize = rows.len();
         let n: usize = cols.len();
-        let mut data: Vec<Complex64> = Vec::with_capacity(m * n);
-
-        // a war with the borrow checker was fought here
-        for &i in &rows { // row major 
-            let xi: &[f64; D] = &target_nodes.points[i];
-            for &j in &cols {
-                let yj: &[f64; D] = &source_nodes.points[j];
-                data.push(kernel.eval(xi, yj));
+        let mut data: Vec<K::Scalar> = vec![K::Scalar::zero(); m * n];
+
+        // whole block in one go, row major
+        kernel.eval_block(target_nodes, &rows, source_nodes, &cols, &mut data);
+        DenseBlock {rows, cols, data}
+    }
+
+
+    // adaptive cross approximation with partial pivoting, only ever touches the rows and columns it needs
+    // stops when the newest rank one term drops below tol relative to the running Frobenius estimate
+    pub fn build_lr_block(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
+        rows: Vec<usize>, cols: Vec<usize>, kernel: &K, tol: f64) -> LowRankBlock<K::Scalar> {
+
+            let m: usize = rows.len();
+            let n: usize = cols.len();
+            let max_rank: usize = m.min(n);
+
+            // factors kept as columns while building, flattened to row major at the end
+            let mut u_cols: Vec<Vec<K::Scalar>> = Vec::new();
+            let mut v_cols: Vec<Vec<K::Scalar>> = Vec::new();
+
+            let mut used_rows: Vec<bool> = vec![false; m];
+            let mut pivot_row: usize = 0;
+            let mut norm2: f64 = 0.0; // ||UV^T||_F^2 estimate
+
+            while u_cols.len() < max_rank {
+                used_rows[pivot_row] = true;
+
+                // residual row at pivot_row
+                let mut row: Vec<K::Scalar> = vec![K::Scalar::zero(); n];
+                kernel.eval_block(target_nodes, &rows[pivot_row..pivot_row + 1], source_nodes, &cols, &mut row);
+                for (u, v) in u_cols.iter().zip(&v_cols) {
+                    for (r, &vj) in row.iter_mut().zip(v) { *r -= u[pivot_row] * vj; }
+                }
+
+                let pivot_col: usize = argmax_abs(&row, |_| true);
+                let pivot: K::Scalar = row[pivot_col];
+
+                // row already fully captured, try another one
+                if pivot.abs() == 0.0 {
+                    match used_rows.iter().position(|&used| !used) {
+                        Some(next) => { pivot_row = next; continue }
+                        None => break,
+                    }
+                }
+
+                // residual column at pivot_col
+                let mut col: Vec<K::Scalar> = vec![K::Scalar::zero(); m];
+                kernel.eval_block(target_nodes, &rows, source_nodes, &cols[pivot_col..pivot_col + 1], &mut col);
+                for (u, v) in u_cols.iter().zip(&v_cols) {
+                    for (c, &ui) in col.iter_mut().zip(u) { *c -= ui * v[pivot_col]; }
+                }
+
+                let v_new: Vec<K::Scalar> = row.iter().map(|&r| r / pivot).collect();
+                let u_new: Vec<K::Scalar> = col;
+
+                // update the Frobenius estimate with the cross terms against the previous factors
+                let u_norm2: f64 = u_new.iter().map(|z| z.abs_sqr()).sum();
+                let v_norm2: f64 = v_new.iter().map(|z| z.abs_sqr()).sum();
+                for (u, v) in u_cols.iter().zip(&v_cols) {
+                    let uu: K::Scalar = u.iter().zip(&u_new).map(|(&a, &b)| a.conj() * b).sum();
+                    let vv: K::Scalar = v.iter().zip(&v_new).map(|(&a, &b)| a.conj() * b).sum();
+                    norm2 += 2.0 * (uu * vv).re();
+                }
+                norm2 += u_norm2 * v_norm2;
+
+                let next_row: usize = argmax_abs(&u_new, |i| !used_rows[i]);
+                u_cols.push(u_new);
+                v_cols.push(v_new);
+
+                if (u_norm2 * v_norm2).sqrt() <= tol * norm2.max(0.0).sqrt() { break }
+                if used_rows.iter().all(|&used| used) { break }
+                pivot_row = next_row;
+            }
+
+            // flatten into row major m x rank and n x rank
+            let rank: usize = u_cols.len();
+            let mut u: Vec<K::Scalar> = Vec::with_capacity(m * rank);
+            for i in 0..m { for col in &u_cols { u.push(col[i]); } }
+            let mut v: Vec<K::Scalar> = Vec::with_capacity(n * rank);
+            for j in 0..n { for col in &v_cols { v.push(col[j]); } }
+
+            LowRankBlock{ rows, cols, rank, u, v, corrections: BTreeMap::new()}
+    }
+
+    // compare every leaf against exact kernel entries, exhaustively if the block has at most max_entries
+    // entries and on max_entries random ones otherwise -- worst blocks show which admissible pairs don't compress
+    pub fn block_errors(&self, target_nodes: &Nodes<D>, source_nodes: &Nodes<D>, max_entries: usize) -> Vec<BlockError> {
+
+        let depths: Vec<usize> = self.block_tree.depths();
+        let mut rng: StdRng = StdRng::seed_from_u64(0);
+        let mut report: Vec<BlockError> = Vec::with_capacity(self.blocks.len());
+
+        for (&block_id, block) in self.leaf_ids.iter().zip(&self.blocks) {
+            let node = &self.block_tree.nodes[block_id];
+
+            let (rows, cols): (&[usize], &[usize]) = (block.rows(), block.cols());
+            let rank: Option<usize> = match block {
+                BlockStorage::Dense(_) => None,
+                BlockStorage::LowRank(lowrank) => Some(lowrank.rank),
+            };
+
+            let (m, n): (usize, usize) = (rows.len(), cols.len());
+            let sampled: bool = m * n > max_entries;
+            let entries: Vec<(usize, usize)> = if sampled {
+                (0..max_entries).map(|_| (rng.gen_range(0..m), rng.gen_range(0..n))).collect()
+            } else {
+                (0..m).flat_map(|bi| (0..n).map(move |bj| (bi, bj))).collect()
+            };
+
+            let mut err2: f64 = 0.0;
+            let mut exact2: f64 = 0.0;
+            for (bi, bj) in entries {
+                let exact: K::Scalar = self.kernel.eval_nodes(target_nodes, rows[bi], source_nodes, cols[bj]);
+                err2 += (exact - block.entry(bi, bj)).abs_sqr();
+                exact2 += exact.abs_sqr();
             }
+            let rel_error: f64 = if exact2 > 0.0 { (err2 / exact2).sqrt() } else { err2.sqrt() };
+
+            report.push(BlockError {
+                block_id, target_index: node.target_index, source_index: node.source_index,
+                depth: depths[block_id], block_type: node.block_type, rank,
+                n_rows: m, n_cols: n, rel_error, sampled,
+            });
         }
-        DenseBlock {rows, cols, data}
+        report
+    }
+
+    // exact diagonal, read straight from the leaves that cover it -- near field entries plus rows of UV^T
+    pub fn diagonal(&self) -> Vec<K::Scalar> {
+        assert_eq!(self.n_rows, self.n_cols, "diagonal needs a square HMatrix");
+        let mut diag: Vec<K::Scalar> = vec![K::Scalar::zero(); self.n_rows];
+
+        for block in &self.blocks {
+            let col_pos: HashMap<usize, usize> = block.cols().iter().enumerate().map(|(bj, &j)| (j, bj)).collect();
+            for (bi, i) in block.rows().iter().enumerate() {
+                if let Some(&bj) = col_pos.get(i) {
+                    diag[*i] = block.entry(bi, bj);
+                }
+            }
+        }
+        diag
+    }
+
+    // ---------- entry access, all in the original Nodes numbering (node * components + component) ----------
+
+    // walk down the BlockTree following the child whose clusters hold (i, j), returns position in blocks
+    // plus the local (row, col) inside that leaf
+    fn locate(&self, i: usize, j: usize) -> (usize, usize, usize) {
+        assert!(i < self.n_rows && j < self.n_cols, "entry ({}, {}) outside {} x {} matrix", i, j, self.n_rows, self.n_cols);
+        let c: usize = self.kernel.components();
+        let mut id: usize = self.block_tree.root_id;
+        while let Some(children) = &self.block_tree.nodes[id].children {
+            id = *children.iter()
+                .find(|&&child_id| {
+                    let child = &self.block_tree.nodes[child_id];
+                    self.target_tree.contains(child.target_index, i / c) && self.source_tree.contains(child.source_index, j / c)
+                })
+                .expect("children of a block partition it");
+        }
+        let node = &self.block_tree.nodes[id];
+        let block_pos: usize = self.leaf_ids.binary_search(&id).expect("leaf has a stored block");
+        (block_pos, self.local_row(node.target_index, i), self.local_col(node.source_index, j))
+    }
+
+    // single entry A_ij, O(depth + rank)
+    pub fn get(&self, i: usize, j: usize) -> K::Scalar {
+        let (block_pos, bi, bj) = self.locate(i, j);
+        self.blocks[block_pos].entry(bi, bj)
+    }
+
+    // overwrite A_ij -- dense leaves just change the entry, low rank ones keep it as a sparse correction on top of UV^T
+    pub fn set(&mut self, i: usize, j: usize, value: K::Scalar) {
+        let (block_pos, bi, bj) = self.locate(i, j);
+        match &mut self.blocks[block_pos] {
+            BlockStorage::Dense(dense) => {
+                let n: usize = dense.cols.len();
+                dense.data[bi * n + bj] = value;
+            }
+            BlockStorage::LowRank(lowrank) => lowrank.set(bi, bj, value),
+        }
+    }
+
+    // full row i, only visits the leaves whose target cluster holds i
+    pub fn row(&self, i: usize) -> Vec<K::Scalar> {
+        assert!(i < self.n_rows);
+        let mut row: Vec<K::Scalar> = vec![K::Scalar::zero(); self.n_cols];
+        let c: usize = self.kernel.components();
+        for block_pos in self.leaves_along(|node| self.target_tree.contains(node.target_index, i / c)) {
+            let block: &BlockStorage<K::Scalar> = &self.blocks[block_pos];
+            let bi: usize = self.local_row(self.block_tree.nodes[self.leaf_ids[block_pos]].target_index, i);
+            for (bj, &j) in block.cols().iter().enumerate() { row[j] = block.entry(bi, bj); }
+        }
+        row
+    }
+
+    // full column j, only visits the leaves whose source cluster holds j
+    pub fn col(&self, j: usize) -> Vec<K::Scalar> {
+        assert!(j < self.n_cols);
+        let mut col: Vec<K::Scalar> = vec![K::Scalar::zero(); self.n_rows];
+        let c: usize = self.kernel.components();
+        for block_pos in self.leaves_along(|node| self.source_tree.contains(node.source_index, j / c)) {
+            let block: &BlockStorage<K::Scalar> = &self.blocks[block_pos];
+            let bj: usize = self.local_col(self.block_tree.nodes[self.leaf_ids[block_pos]].source_index, j);
+            for (bi, &i) in block.rows().iter().enumerate() { col[i] = block.entry(bi, bj); }
+        }
+        col
+    }
+
+    // A[rows, cols] as a len(rows) x len(cols) row major matrix
+    pub fn submatrix(&self, rows: &[usize], cols: &[usize]) -> Vec<K::Scalar> {
+        let mut data: Vec<K::Scalar> = Vec::with_capacity(rows.len() * cols.len());
+        for &i in rows {
+            for &j in cols {
+                data.push(self.get(i, j));
+            }
+        }
+        data
+    }
+
+    // where unknown i sits in the expanded rows of a leaf on target cluster node_id, local_col likewise for sources
+    fn local_row(&self, node_id: usize, i: usize) -> usize {
+        let c: usize = self.kernel.components();
+        self.target_tree.local_index(node_id, i / c) * c + i % c
+    }
+
+    fn local_col(&self, node_id: usize, j: usize) -> usize {
+        let c: usize = self.kernel.components();
+        self.source_tree.local_index(node_id, j / c) * c + j % c
+    }
+
+    // positions in blocks of every leaf reachable through BlockNodes that pass keep
+    fn leaves_along(&self, keep: impl Fn(&crate::block::BlockNode) -> bool) -> Vec<usize> {
+        let mut found: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = vec![self.block_tree.root_id];
+        while let Some(id) = stack.pop() {
+            let node = &self.block_tree.nodes[id];
+            if !keep(node) { continue }
+            match &node.children {
+                Some(children) => stack.extend(children),
+                None => found.push(self.leaf_ids.binary_search(&id).expect("leaf has a stored block")),
+            }
+        }
+        found
+    }
+
+    // scatter every leaf back into one n_rows x n_cols row major matrix, only sensible for small problems
+    pub fn to_dense(&self) -> Vec<K::Scalar> {
+        let mut data: Vec<K::Scalar> = vec![K::Scalar::zero(); self.n_rows * self.n_cols];
+
+        for block in &self.blocks {
+            let (rows, cols, local): (&[usize], &[usize], Vec<K::Scalar>) = (block.rows(), block.cols(), block.to_dense());
+            for (bi, &i) in rows.iter().enumerate() {
+                for (bj, &j) in cols.iter().enumerate() {
+                    data[i * self.n_cols + j] = local[bi * cols.len() + bj];
+                }
+            }
+        }
+        data
     }
+}
 
+// cluster node indices -> unknowns node * c + component, in node order so each node's c unknowns stay together
+fn expand(indices: &[usize], c: usize) -> Vec<usize> {
+    indices.iter().flat_map(|&i| (0..c).map(move |a| i * c + a)).collect()
+}
+
+// index of the largest magnitude entry among those allowed by keep (0 if none are allowed)
+fn argmax_abs<T: Scalar>(values: &[T], keep: impl Fn(usize) -> bool) -> usize {
+    let mut best: usize = 0;
+    let mut best_val: f64 = -1.0;
+    for (i, z) in values.iter().enumerate() {
+        if keep(i) && z.abs() > best_val {
+            best = i;
+            best_val = z.abs();
+        }
+    }
+    best
+}
 
-    pub fn build_LR_block(target_nodes: &Nodes<D>, source_nodes: &Nodes<D>,
-        rows: Vec<usize>, cols: Vec<usize>, kernel: &K) -> LowRankBlock {
+#[cfg(test)]
+mod hmatrix_tests {
+    use super::*;
+    use num_complex::Complex64;
+    use crate::kernels::{Laplace, Helmholtz, Weighted, SelfTerm, FnKernel, AxisymmetricHelmholtz};
+    use crate::operator::LinearOperator;
+    use crate::functions::{cardioid_nodes, cardioid_boundary, approximation_error, dense_matrix};
+
+    #[test]
+    fn block_errors_cover_every_leaf() {
+        let nodes: Nodes<2> = cardioid_boundary(100);
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, Laplace);
+
+        let report: Vec<BlockError> = hmat.block_errors(&nodes, &nodes, 32);
+        assert_eq!(report.len(), hmat.blocks.len());
+
+        for entry in &report {
+            assert!(hmat.block_tree.nodes[entry.block_id].children.is_none());
+            match entry.block_type {
+                BlockType::Near => { assert!(entry.rank.is_none()); assert!(entry.rel_error < 1e-14) }
+                BlockType::Far => assert!(entry.rel_error < 1e-6, "{:?}", entry),
+            }
+            assert_eq!(entry.sampled, entry.n_rows * entry.n_cols > 32);
+        }
+    }
+
+    #[test]
+    fn entry_access_matches_dense() {
+        let nodes: Nodes<2> = cardioid_boundary(75);
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 6);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let mut hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, Laplace);
+        let dense: Vec<f64> = hmat.to_dense();
+
+        for i in (0..75).step_by(7) {
+            for j in (0..75).step_by(5) {
+                assert_eq!(hmat.get(i, j), dense[i * 75 + j]);
+            }
+            assert_eq!(hmat.row(i), dense[i * 75..(i + 1) * 75].to_vec());
+            let col: Vec<f64> = (0..75).map(|r| dense[r * 75 + i]).collect();
+            assert_eq!(hmat.col(i), col);
+        }
+
+        let rows: [usize; 3] = [4, 60, 11];
+        let cols: [usize; 2] = [70, 0];
+        let sub: Vec<f64> = hmat.submatrix(&rows, &cols);
+        for (a, &i) in rows.iter().enumerate() {
+            for (b, &j) in cols.iter().enumerate() {
+                assert_eq!(sub[a * 2 + b], dense[i * 75 + j]);
+            }
+        }
+
+        // one entry in a dense leaf and one in a low rank leaf
+        let value: f64 = 3.0;
+        hmat.set(3, 3, value);
+        hmat.set(0, 37, value);
+        assert!((hmat.get(3, 3) - value).abs() < 1e-12);
+        assert!((hmat.get(0, 37) - value).abs() < 1e-12);
+        assert!((hmat.get(1, 37) - dense[75 + 37]).abs() < 1e-12);
+
+        // setting the same low rank entry again replaces the correction, the rank stays put
+        let (block_pos, _, _) = hmat.locate(0, 37);
+        let rank = |hmat: &HMatrix<2, Laplace>| match &hmat.blocks[block_pos] { BlockStorage::LowRank(b) => (b.rank, b.corrections.len()), _ => panic!() };
+        let before: (usize, usize) = rank(&hmat);
+        for step in 0..50 { hmat.set(0, 37, step as f64); }
+        assert_eq!(rank(&hmat), before);
+        assert_eq!(hmat.get(0, 37), 49.0);
+
+        // and apply sees it
+        let x: Vec<f64> = (0..75).map(|j| (j as f64).sin()).collect();
+        let dense: Vec<f64> = hmat.to_dense();
+        assert_eq!(dense[37], 49.0);
+        let y: Vec<f64> = hmat.apply(&x);
+        for (i, yi) in y.iter().enumerate() {
+            let exact: f64 = (0..75).map(|j| dense[i * 75 + j] * x[j]).sum();
+            assert!((yi - exact).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn absorbing_far_field_is_dropped() {
+        // long helix, k = 4 + 6i decays by 1e-8 over about 3 units so the far ends never see each other
+        let points: Vec<[f64; 3]> = (0..400).map(|i| {
+            let t: f64 = 0.05 * i as f64;
+            [t.cos(), t.sin(), 0.5 * t]
+        }).collect();
+        let nodes: Nodes<3> = Nodes::new(points);
+        let tree: ClusterTree<3> = ClusterTree::build_tree(&nodes, 16);
+
+        // nothing to say about a non oscillating, non decaying kernel so it's the plain tree
+        let plain: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        assert_eq!(BlockTree::build_tree_for_kernel(&tree, &tree, 1.0, &Laplace, ACA_TOL).leaf_ids(), plain.leaf_ids());
+
+        let kernel: Helmholtz = Helmholtz::new(Complex64::new(4.0, 6.0));
+        // no 3D self term, and the diagonal isn't what this is about, so zero it
+        let diagonal = |_: &Nodes<3>, _: usize| Complex64::new(0.0, 0.0);
+        let blocks: BlockTree = BlockTree::build_tree_for_kernel(&tree, &tree, 1.0, &kernel, ACA_TOL);
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, SelfTerm::new(kernel, diagonal));
+        let dropped: usize = hmat.blocks.iter().filter(|block| matches!(block, BlockStorage::LowRank(lowrank) if lowrank.rank == 0)).count();
+        assert!(dropped > 0);
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-7);
+
+        // a looser tolerance gives up on closer pairs, in the tree and in assembly alike
+        let loose: BlockTree = BlockTree::build_tree_for_kernel(&tree, &tree, 1.0, &kernel, 1e-3);
+        let hmat = HMatrix::assemble_with_tol(&nodes, &nodes, &tree, &tree, loose, SelfTerm::new(kernel, diagonal), 1e-3);
+        let dropped_loose: usize = hmat.blocks.iter().filter(|block| matches!(block, BlockStorage::LowRank(lowrank) if lowrank.rank == 0)).count();
+        assert!(dropped_loose > dropped, "{} {}", dropped_loose, dropped);
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-2);
+    }
+
+    #[test]
+    fn wavenumber_derivative_shares_block_tree() {
+        let nodes: Nodes<2> = cardioid_boundary(120);
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let k: f64 = 5.0;
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, Weighted::new(Helmholtz::new(k)));
+        let dk = hmat.assemble_dk(&nodes, &nodes);
+        assert_eq!(dk.leaf_ids, hmat.leaf_ids);
+        assert!(Arc::ptr_eq(&hmat.target_tree, &hmat.source_tree));
+        assert!(Arc::ptr_eq(&dk.target_tree, &hmat.target_tree) && Arc::ptr_eq(&dk.source_tree, &hmat.source_tree));
+
+        // central difference of the full Nystrom matrix in k
+        let h: f64 = 1e-5;
+        let plus: Vec<Complex64> = dense_matrix(&nodes, &nodes, &Weighted::new(Helmholtz::new(k + h)));
+        let minus: Vec<Complex64> = dense_matrix(&nodes, &nodes, &Weighted::new(Helmholtz::new(k - h)));
+        let fd: Vec<Complex64> = plus.iter().zip(&minus).map(|(&p, &m)| (p - m) / (2.0 * h)).collect();
+        let approx: Vec<Complex64> = dk.to_dense();
+        let err: f64 = fd.iter().zip(&approx).map(|(a, b)| (a - b).norm_sqr()).sum::<f64>().sqrt();
+        let norm: f64 = fd.iter().map(|a| a.norm_sqr()).sum::<f64>().sqrt();
+        assert!(err < 1e-7 * norm, "{}", err / norm);
+    }
+
+    #[test]
+    fn closure_kernel_assembles() {
+        // exponential covariance written inline, no struct needed
+        let nodes: Nodes<2> = Nodes::new(cardioid_nodes(150));
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let kernel = FnKernel::new(|x: &[f64; 2], y: &[f64; 2]| (-((x[0] - y[0]).powi(2) + (x[1] - y[1]).powi(2)).sqrt()).exp());
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, kernel);
+        assert!(hmat.blocks.iter().any(|block| matches!(block, BlockStorage::LowRank(_))));
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-6);
+    }
 
-            LowRankBlock{ rows, cols}
+    #[test]
+    fn axisymmetric_generating_curve_assembles() {
+        // upper half of the cardioid spun about its symmetry axis, so rho = y and z = x
+        let points: Vec<[f64; 2]> = cardioid_nodes(300).into_iter().filter(|p| p[1] > 0.0).map(|p| [p[1], p[0]]).collect();
+        let nodes: Nodes<2> = Nodes::new(points);
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+        let kernel: AxisymmetricHelmholtz = AxisymmetricHelmholtz::new(4.0, 2);
+        let blocks: BlockTree = BlockTree::build_tree_for_kernel(&tree, &tree, 0.5, &kernel, ACA_TOL);
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, kernel);
+        assert!(hmat.blocks.iter().any(|block| matches!(block, BlockStorage::LowRank(_))));
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-6);
     }
 }
@@ -1,28 +1,351 @@
-use distances::vectors::euclidean;
-use num_complex::{Complex, Complex64};
-use std::f64::consts::{PI,E};
-use scilib::math::bessel::*; // hankel function
-use num_complex::ComplexFloat; // complex exponents
+use num_complex::Complex64;
+use std::f64::consts::PI;
+use crate::nodes::Nodes;
+use crate::special::{bessel_k, bessel_k0, gamma, hankel1_0, hankel1_01_complex, exp_integral, faddeeva, toroidal_q, gauss_legendre_16};
+use crate::scalar::Scalar;
 
 // Kernels saved as traits for independence
-pub trait Kernel<const D: usize> { 
-    fn eval(&self, x: &[f64; D], y: &[f64; D]) -> Complex64; // require generic 2 point eval returning a Complex64
+// Kernel is everything assembly needs and only ever sees Nodes, so layer potentials (normals) and tensor kernels
+// (unknown indices) fit it too. kernels that are a plain function of two points also implement PointKernel below
+pub trait Kernel<const D: usize> {
+    type Scalar: Scalar; // f64 for real kernels (Laplace, covariances), Complex64 for oscillatory ones
+
+    // what assembly actually calls: entry (i, j) between target node i and source node j
+    // point kernels hand this to point_eval_nodes, layer potentials pick up normals etc from Nodes
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> Self::Scalar;
+
+    // self interaction (diagonal) value for source node i, used by eval_nodes when target and source are the same node
+    // None means the kernel has nothing better than eval at r = 0, which the singular ones refuse -- wrap in SelfTerm then
+    fn eval_self(&self, _nodes: &Nodes<D>, _i: usize) -> Option<Self::Scalar> { None }
+
+    // hints for BlockTree::build_tree_for_kernel and assembly, only used to decide which blocks are far/negligible
+    // decay_rate: a with |K(r)| ~ e^{-a r} for large r, eg. Im k for Helmholtz. oscillation: phase per unit length, eg. Re k
+    fn decay_rate(&self) -> f64 { 0.0 }
+    fn oscillation(&self) -> f64 { 0.0 }
+
+    // unknowns per node, 1 for scalar kernels and D for the tensor ones in tensor.rs -- HMatrix rows and cols then
+    // run over node * components + component, which is also what the indices passed to eval_nodes / eval_block mean
+    fn components(&self) -> usize { 1 }
+
+    // every entry (rows[a], cols[b]) at once into out, row major len(rows) x len(cols) -- what block assembly calls
+    // default just loops eval_nodes, kernels that only need r override it with a structure of arrays version
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [Self::Scalar]) {
+        assert_eq!(out.len(), rows.len() * cols.len(), "out must be len(rows) x len(cols)");
+        let n: usize = cols.len();
+        for (a, &i) in rows.iter().enumerate() {
+            for (b, &j) in cols.iter().enumerate() {
+                out[a * n + b] = self.eval_nodes(targets, i, sources, j);
+            }
+        }
+    }
+}
+
+// G(x, y) from the two positions alone -- Laplace, Helmholtz, covariances, closures. anything that needs a bare
+// two point kernel (HalfSpace images, finite difference checks) asks for this rather than Kernel, so handing it
+// a layer potential is a compile error instead of a panic
+pub trait PointKernel<const D: usize>: Kernel<D> {
+    fn eval(&self, x: &[f64; D], y: &[f64; D]) -> Self::Scalar;
+}
+
+// Kernel::eval_nodes for point kernels: the self term on the diagonal, eval everywhere else
+pub fn point_eval_nodes<const D: usize, K: PointKernel<D>>(kernel: &K, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+    coincident_self(kernel, targets, i, sources, j).unwrap_or_else(|| kernel.eval(&targets.points[i], &sources.points[j]))
+}
+
+// target i and source j are the same node, ie. the same Nodes and the same index. two different nodes that happen to sit
+// on the same point are not a diagonal entry and go to eval like everything else
+fn is_diagonal<const D: usize>(targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> bool {
+    std::ptr::eq(targets, sources) && i == j
+}
+
+// eval_self on the diagonal, None off it or if the kernel has no self term
+fn coincident_self<const D: usize, K: Kernel<D>>(kernel: &K, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> Option<K::Scalar> {
+    if is_diagonal(targets, i, sources, j) { kernel.eval_self(sources, j) } else { None }
+}
+
+// the log and 1/r kernels have no value at r = 0, so rather than hand back something finite and meaningless they stop here
+fn assert_separated(name: &str, r: f64) {
+    assert!(r > 0.0, "{} is singular at r = 0: give the Nodes quadrature weights (2D) or wrap the kernel in SelfTerm to supply the diagonal", name);
+}
+
+// one unknown per node, ie. components() == 1 -- every kernel in this file, just not the tensor ones. wrappers that only
+// make sense per node (SelfTerm's diagonal, HalfSpace's images, Sum) ask for it in their bounds, see the impls at the end
+pub trait ScalarKernel<const D: usize>: Kernel<D> {}
+
+// r without going through a Vec like euclidean does
+fn distance<const D: usize>(x: &[f64; D], y: &[f64; D]) -> f64 {
+    x.iter().zip(y).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt()
+}
+
+// |x_i - y_j|^2 for every i in rows, j in cols, row major. source coordinates are gathered into one contiguous array
+// per dimension first so the inner loop is a straight run over f64s the compiler can vectorise
+fn squared_distances<const D: usize>(targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize]) -> Vec<f64> {
+    let n: usize = cols.len();
+    let source_coords: Vec<Vec<f64>> = (0..D).map(|d| cols.iter().map(|&j| sources.points[j][d]).collect()).collect();
+    let mut r2: Vec<f64> = vec![0.0; rows.len() * n];
+    for (&i, r2_row) in rows.iter().zip(r2.chunks_exact_mut(n.max(1))) {
+        for (&xd, ys) in targets.points[i].iter().zip(&source_coords) {
+            for (out, &yd) in r2_row.iter_mut().zip(ys) {
+                let diff: f64 = xd - yd;
+                *out += diff * diff;
+            }
+        }
+    }
+    r2
+}
+
+// squared distances -> kernel values, coincident pairs (r = 0) go through `coincident` so self terms still apply
+fn fill_radial<T: Scalar>(r2: &[f64], n: usize, out: &mut [T], radial: impl Fn(f64) -> T, coincident: impl Fn(usize, usize) -> T) {
+    assert_eq!(out.len(), r2.len(), "out must be len(rows) x len(cols)");
+    for (k, (value, &d)) in out.iter_mut().zip(r2).enumerate() {
+        *value = if d > 0.0 { radial(d) } else { coincident(k / n, k % n) };
+    }
+}
+
+// any kernel with a caller supplied diagonal -- eg. SelfTerm::new(Laplace, |nodes: &Nodes<3>, i| ...)
+// everything off the diagonal goes straight to the wrapped kernel
+pub struct SelfTerm<K, F> { pub kernel: K, pub diagonal: F }
+
+impl<K, F> SelfTerm<K, F> {
+    pub fn new(kernel: K, diagonal: F) -> Self { Self {kernel, diagonal}}
+}
+
+impl<const D: usize, K: ScalarKernel<D>, F: Fn(&Nodes<D>, usize) -> K::Scalar> Kernel<D> for SelfTerm<K, F> {
+    type Scalar = K::Scalar;
+
+    // wrapped kernel might override eval_nodes (layer potentials) so check the diagonal here rather than rely on the default
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        if is_diagonal(targets, i, sources, j) { return (self.diagonal)(sources, j) }
+        self.kernel.eval_nodes(targets, i, sources, j)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K::Scalar> { Some((self.diagonal)(nodes, i)) }
+
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+}
+
+impl<const D: usize, K: PointKernel<D> + ScalarKernel<D>, F: Fn(&Nodes<D>, usize) -> K::Scalar> PointKernel<D> for SelfTerm<K, F> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> K::Scalar { self.kernel.eval(x, y) }
+}
+
+// K(x_i, y_j) w_j, the kernel times the source quadrature weight, so an HMatrix of this is the Nystrom matrix and a
+// matvec with it is the quadrature of the integral operator -- source Nodes without weights get w_j = 1
+// the weight lives on a node, so this is a Kernel but never a PointKernel
+#[derive(Clone, Copy)]
+pub struct Weighted<K> { pub kernel: K }
+
+impl<K> Weighted<K> {
+    pub fn new(kernel: K) -> Self { Self {kernel}}
+}
+
+impl<const D: usize, K: Kernel<D>> Kernel<D> for Weighted<K> {
+    type Scalar = K::Scalar;
+
+    // j is an unknown, the weight belongs to its node
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes(targets, i, sources, j) * K::Scalar::from_real(sources.weight(j / self.components()))
+    }
+
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K::Scalar> {
+        self.kernel.eval_self(nodes, i).map(|value| value * K::Scalar::from_real(nodes.weight(i / self.components())))
+    }
+
+    // keep the wrapped kernel's fast block path, then scale the columns
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [K::Scalar]) {
+        self.kernel.eval_block(targets, rows, sources, cols, out);
+        let c: usize = self.components();
+        let weights: Vec<K::Scalar> = cols.iter().map(|&j| K::Scalar::from_real(sources.weight(j / c))).collect();
+        for row in out.chunks_exact_mut(cols.len().max(1)) {
+            for (value, &w) in row.iter_mut().zip(&weights) { *value *= w; }
+        }
+    }
+
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+    fn components(&self) -> usize { self.kernel.components() }
+}
+
+// ---------------- COMBINATORS ----------------------
+
+// c K -- factor f64 (the default) scales any kernel, Complex64 scales complex kernels, eg. Scaled::new(Helmholtz::new(3.0), -Complex64::i())
+#[derive(Clone, Copy)]
+pub struct Scaled<K, T = f64> { pub kernel: K, pub factor: T }
+
+impl<K, T> Scaled<K, T> {
+    pub fn new(kernel: K, factor: T) -> Self { Self {kernel, factor}}
+}
+
+impl<const D: usize, K: Kernel<D>> Kernel<D> for Scaled<K, f64> {
+    type Scalar = K::Scalar;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes(targets, i, sources, j) * K::Scalar::from_real(self.factor)
+    }
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K::Scalar> {
+        self.kernel.eval_self(nodes, i).map(|value| value * K::Scalar::from_real(self.factor))
+    }
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [K::Scalar]) {
+        self.kernel.eval_block(targets, rows, sources, cols, out);
+        out.iter_mut().for_each(|value| *value *= K::Scalar::from_real(self.factor));
+    }
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+    fn components(&self) -> usize { self.kernel.components() }
+}
+
+impl<const D: usize, K: Kernel<D, Scalar = Complex64>> Kernel<D> for Scaled<K, Complex64> {
+    type Scalar = Complex64;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> Complex64 {
+        self.kernel.eval_nodes(targets, i, sources, j) * self.factor
+    }
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<Complex64> {
+        self.kernel.eval_self(nodes, i).map(|value| value * self.factor)
+    }
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [Complex64]) {
+        self.kernel.eval_block(targets, rows, sources, cols, out);
+        out.iter_mut().for_each(|value| *value *= self.factor);
+    }
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+    fn components(&self) -> usize { self.kernel.components() }
+}
+
+impl<const D: usize, K: PointKernel<D>> PointKernel<D> for Scaled<K, f64> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> K::Scalar { self.kernel.eval(x, y) * K::Scalar::from_real(self.factor) }
+}
+
+impl<const D: usize, K: PointKernel<D, Scalar = Complex64>> PointKernel<D> for Scaled<K, Complex64> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> Complex64 { self.kernel.eval(x, y) * self.factor }
+}
+
+// K1 + K2 with the same scalar, each half keeps its own eval_nodes / self term / block fast path
+// scalar kernels here, tensor.rs adds the TensorKernel version so Tensor::new(Sum::new(..)) works too
+#[derive(Clone, Copy)]
+pub struct Sum<K1, K2> { pub first: K1, pub second: K2 }
+
+impl<K1, K2> Sum<K1, K2> {
+    pub fn new(first: K1, second: K2) -> Self { Self {first, second}}
+}
+
+impl<const D: usize, K1: ScalarKernel<D>, K2: ScalarKernel<D, Scalar = K1::Scalar>> Kernel<D> for Sum<K1, K2> {
+    type Scalar = K1::Scalar;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K1::Scalar {
+        self.first.eval_nodes(targets, i, sources, j) + self.second.eval_nodes(targets, i, sources, j)
+    }
+
+    // only one half might have a self term, the other then just goes through its own eval_nodes
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K1::Scalar> {
+        let (first, second) = (self.first.eval_self(nodes, i), self.second.eval_self(nodes, i));
+        if first.is_none() && second.is_none() { return None }
+        Some(first.unwrap_or_else(|| self.first.eval_nodes(nodes, i, nodes, i))
+            + second.unwrap_or_else(|| self.second.eval_nodes(nodes, i, nodes, i)))
+    }
+
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [K1::Scalar]) {
+        self.first.eval_block(targets, rows, sources, cols, out);
+        let mut second: Vec<K1::Scalar> = vec![K1::Scalar::zero(); out.len()];
+        self.second.eval_block(targets, rows, sources, cols, &mut second);
+        for (value, extra) in out.iter_mut().zip(second) { *value += extra; }
+    }
+
+    // far/negligible only as far as the slower decaying, faster oscillating half allows
+    fn decay_rate(&self) -> f64 { self.first.decay_rate().min(self.second.decay_rate()) }
+    fn oscillation(&self) -> f64 { self.first.oscillation().max(self.second.oscillation()) }
+}
+
+impl<const D: usize, K1: PointKernel<D> + ScalarKernel<D>, K2: PointKernel<D, Scalar = K1::Scalar> + ScalarKernel<D>> PointKernel<D> for Sum<K1, K2> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> K1::Scalar { self.first.eval(x, y) + self.second.eval(x, y) }
+}
+
+// any closure of two points as a kernel, for prototyping without a new struct -- the scalar is whatever it returns
+// eg. FnKernel::new(|x: &[f64; 2], y: &[f64; 2]| (-(x[0] - y[0]).abs()).exp()) then HMatrix::assemble as usual
+#[derive(Clone, Copy)]
+pub struct FnKernel<F> { pub f: F }
+
+impl<F> FnKernel<F> {
+    pub fn new(f: F) -> Self { Self {f}}
+}
+
+impl<const D: usize, T: Scalar, F: Fn(&[f64; D], &[f64; D]) -> T> Kernel<D> for FnKernel<F> {
+    type Scalar = T;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> T {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+}
+
+impl<const D: usize, T: Scalar, F: Fn(&[f64; D], &[f64; D]) -> T> PointKernel<D> for FnKernel<F> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> T { (self.f)(x, y) }
+}
+
+// ---------------- 2D SELF TERMS ----------------------
+
+// Nystrom diagonals for the periodic trapezoid rule on a closed curve, nodes at t_i = 2 pi i / n with arclength weights
+// w_i = |x'(t_i)| 2pi/n (eg. cardioid_boundary). split -log|x(t) - x(s)| / 2pi into -log(4 sin^2((t-s)/2)) / 4pi plus
+// a smooth part tending to -log|x'(t)| / 2pi. the trapezoid sum of log(4 sin^2(pi j/n)) over j != 0 is 2 log n while the
+// integral is 0, so taking that out through the diagonal leaves -log(w_i / 2pi) / 2pi (Kress / Kapur-Rokhlin order zero)
+const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+
+fn laplace_self_2d(nodes: &Nodes<2>, i: usize) -> Option<f64> {
+    let weight: f64 = nodes.weights.as_ref()?[i];
+    if weight == 0.0 { return Some(0.0) } // eg. the cardioid cusp, entry gets multiplied by zero anyway
+    Some(-(weight / (2.0 * PI)).ln() / (2.0 * PI))
+}
+
+// G - G_laplace is smooth with limit c at r = 0: i/4 - (log(k/2) + gamma) / 2pi for i/4 H0(kr),
+// -(log(lambda/2) + gamma) / 2pi for K0(lambda r) / 2pi
+fn helmholtz_self_constant_2d(k: Complex64) -> Complex64 {
+    Complex64::new(0.0, 0.25) - ((k / 2.0).ln() + EULER_GAMMA) / (2.0 * PI)
 }
 
+
 // ---------------- LAPLACE KERNEL ----------------------
 pub struct Laplace; 
 
-impl<const D: usize> Kernel<D> for Laplace {
+// only 2D and 3D implement Kernel, so eg. Laplace on Nodes<4> is a compile error rather than a panic mid assembly
+impl Kernel<2> for Laplace {
+    type Scalar = f64; // no point carrying an imaginary part around
+
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<f64> { laplace_self_2d(nodes, i) }
+
+    // log r = log(r^2) / 2 so the block never takes a square root
+    fn eval_block(&self, targets: &Nodes<2>, rows: &[usize], sources: &Nodes<2>, cols: &[usize], out: &mut [f64]) {
+        let r2: Vec<f64> = squared_distances(targets, rows, sources, cols);
+        fill_radial(&r2, cols.len(), out, |d| -d.ln() / (4.0 * PI),
+            |a, b| self.eval_nodes(targets, rows[a], sources, cols[b]));
+    }
+}
+
+impl PointKernel<2> for Laplace {
+    fn eval( &self, x: &[f64; 2], y: &[f64; 2]) -> f64 {
+        let r: f64 = distance(x, y);
+        assert_separated("Laplace", r);
+        - (1.0 / (2.0 * PI)) * r.ln()
+    }
+}
+
+impl Kernel<3> for Laplace {
+    type Scalar = f64;
+
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
 
-    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> Complex64 {
-        
-        let temp_r: f64 = euclidean(x, y);
-        let r: f64 = temp_r.max(1e-15); // must find neater way of dealing with r=0
+    fn eval_block(&self, targets: &Nodes<3>, rows: &[usize], sources: &Nodes<3>, cols: &[usize], out: &mut [f64]) {
+        let r2: Vec<f64> = squared_distances(targets, rows, sources, cols);
+        fill_radial(&r2, cols.len(), out, |d| 1.0 / (4.0 * PI * d.sqrt()),
+            |a, b| self.eval_nodes(targets, rows[a], sources, cols[b]));
+    }
+}
 
-        // Laplace Green's functions for 2 and 3 dimensions
-        if D == 2 { return Complex { re:- (1.0 / (2.0 * std::f64::consts::PI)) * r.ln(), im: 0.0}}
-        if D == 3 { return Complex64 { re: 1.0 / (4.0 * std::f64::consts::PI * r), im: 0.0 }}
-        else { panic!()} // must be better way of doing this, maybe in Nodes new impl
+impl PointKernel<3> for Laplace {
+    fn eval( &self, x: &[f64; 3], y: &[f64; 3]) -> f64 {
+        let r: f64 = distance(x, y);
+        assert_separated("Laplace", r);
+        1.0 / (4.0 * PI * r)
     }
 }
 
@@ -30,80 +353,1704 @@ impl<const D: usize> Kernel<D> for Laplace {
 
 // ----- Standard ---------
 
-pub struct Helmholtz { pub wavenumber: f64}
-
-// "new" method for ease of setting k -- eg. Helmholtz::new(3.02)
-impl Helmholtz { pub fn new(wavenumber: f64) -> Self { Self {wavenumber}}}
-
-impl<const D: usize> Kernel<D> for Helmholtz {
-
-    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> Complex64 {
-        
-        let temp_r: f64 = euclidean(x, y);
-        let r: f64 = temp_r.max(1e-15); // must find neater way of dealing with r=0
-
-        // Helmholtz Green's functions for 2 and 3 dimensions
-        if D == 2 {
-            let kr: Complex<f64> = Complex64 {re: self.wavenumber * r, im: 0.0};
-            let h0: Complex<f64> = h1_nu(0.0,kr); 
-            return (Complex64::i()/4.0) * h0 
-        }
-        if D == 3 {
-            let ikr: Complex<f64> = Complex64 {re: 0.0, im: self.wavenumber * r};
-            let exponent: Complex<f64> = E.powc(ikr);
-            return - (1.0/(4.0 * PI * r)) * exponent
-        }
-        else { panic!()} // must be better way of doing this, maybe in Nodes new impl
-    }
-}
-
-// beyond this is not yet working, need clearer picture of nodes structure before willing to put anything down
-// ------- Normal Derivative ----------
-
-//pub struct HelmholtzNormal { pub wavenumber: f64} 
-//
-//// new method for ease of setting k -- eg. HelmholtzNormal::new(3.02)
-//impl HelmholtzNormal {
-    //pub fn new(wavenumber: f64) -> Self { Self {wavenumber}}
-    //} // only really important for multiple calls per k
-//
-//// implementing Kernel trait for 2D HelmholtzNormal
-//impl Kernel<2> for HelmholtzNormal {
-//
-    //// Green function eval method 
-    //fn eval( &self, x: &[f64; 2], y: &[f64; 2]) -> Complex64 {
-        //// bog standard
-        //let dx = x[0] - y[0];
-        //let dy = x[1] - y[1];
-        //let r2 = dx*dx + dy*dy;
-        //let r = r2.max(1e-15).sqrt();
-//
-        //// kr and hankel stuff needed
-        //// let kr = Self.k * r;
-        //// let h0 = hankel0_1(kr); find the fast hankel crate and implement 
-//
-        //Complex64::i() * PI * r * 0.25 
-//        
-    //}
-//}
-
-// implementing Kernel trait for 3D Helmholtz
-//impl Kernel<3> for HelmholtzNormal {
-//
-    //// Green function eval method -- probably need to add new trait about G or dG 
-    //fn eval( &self, x: &[f64; 3], y: &[f64; 3]) -> Complex64 {
-        //// bog standard
-        //let dx = x[0] - y[0];
-        //let dy = x[1] - y[1];
-        //let dz = x[2] - y[2];
-        //let r2 = dx*dx + dy*dy + dz*dz;
-        //let r = r2.max(1e-15).sqrt();
-//
-        //// kr and hankel stuff needed
-        //// let kr = Self.k * r;
-        //// let h0 = hankel0_1(kr); find the fast hankel crate and implement 
-//
-        //Complex64::i() * PI * r * 0.25 
-//        
-    //}
-//}
+// complex k = k_r + i k_i is an absorbing medium, the kernel then decays like e^{-k_i r}
+#[derive(Clone, Copy)]
+pub struct Helmholtz { pub wavenumber: Complex64}
+
+// "new" method for ease of setting k -- eg. Helmholtz::new(3.02) or Helmholtz::new(Complex64::new(3.0, 0.5))
+impl Helmholtz { pub fn new(wavenumber: impl Into<Complex64>) -> Self { Self {wavenumber: wavenumber.into()}}}
+
+impl Kernel<2> for Helmholtz {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> {
+        let laplace: f64 = laplace_self_2d(nodes, i)?;
+        if nodes.weight(i) == 0.0 { return Some(Complex64::new(0.0, 0.0)) }
+        Some(laplace + helmholtz_self_constant_2d(self.wavenumber))
+    }
+
+    fn eval_block(&self, targets: &Nodes<2>, rows: &[usize], sources: &Nodes<2>, cols: &[usize], out: &mut [Complex64]) {
+        let r2: Vec<f64> = squared_distances(targets, rows, sources, cols);
+        fill_radial(&r2, cols.len(), out, |d| helmholtz_2d(self.wavenumber, d.sqrt()),
+            |a, b| self.eval_nodes(targets, rows[a], sources, cols[b]));
+    }
+}
+
+impl PointKernel<2> for Helmholtz {
+    // i/4 H0^(1)(kr)
+    fn eval( &self, x: &[f64; 2], y: &[f64; 2]) -> Complex64 {
+        let r: f64 = distance(x, y);
+        assert_separated("Helmholtz", r);
+        helmholtz_2d(self.wavenumber, r)
+    }
+}
+
+impl Kernel<3> for Helmholtz {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    fn eval_block(&self, targets: &Nodes<3>, rows: &[usize], sources: &Nodes<3>, cols: &[usize], out: &mut [Complex64]) {
+        let r2: Vec<f64> = squared_distances(targets, rows, sources, cols);
+        fill_radial(&r2, cols.len(), out, |d| helmholtz_3d(self.wavenumber, d.sqrt()),
+            |a, b| self.eval_nodes(targets, rows[a], sources, cols[b]));
+    }
+}
+
+impl PointKernel<3> for Helmholtz {
+    // e^{ikr} / (4 pi r)
+    fn eval( &self, x: &[f64; 3], y: &[f64; 3]) -> Complex64 {
+        let r: f64 = distance(x, y);
+        assert_separated("Helmholtz", r);
+        helmholtz_3d(self.wavenumber, r)
+    }
+}
+
+// i/4 H0^(1)(kr), real k keeps the Chebyshev fits
+fn helmholtz_2d(k: Complex64, r: f64) -> Complex64 {
+    let h0: Complex64 = if k.im == 0.0 { hankel1_0(k.re * r) } else { hankel1_01_complex(k * r).0 };
+    (Complex64::i()/4.0) * h0
+}
+
+// e^{ikr} / (4 pi r), same sign as Laplace so k -> 0 gives 1/(4 pi r)
+fn helmholtz_3d(k: Complex64, r: f64) -> Complex64 {
+    (Complex64::i() * k * r).exp() / (4.0 * PI * r)
+}
+
+// ------------------ MODIFIED HELMHOLTZ (YUKAWA) KERNEL ------------------
+
+// Green's function of -Laplace + lambda^2, shows up in implicit time stepping and screened Poisson
+// decays like e^{-lambda r} so the far field compresses really well
+pub struct ModifiedHelmholtz { pub lambda: f64}
+
+impl ModifiedHelmholtz {
+    pub fn new(lambda: f64) -> Self { Self {lambda}}
+
+    // real valued fast path, K0(lambda r) / 2pi. infinite at r = 0, eval_nodes hands the diagonal to eval_self
+    pub fn eval_2d(&self, x: &[f64; 2], y: &[f64; 2]) -> f64 {
+        let r: f64 = distance(x, y);
+        assert_separated("ModifiedHelmholtz", r);
+        bessel_k0(self.lambda * r) / (2.0 * PI)
+    }
+
+    // real valued fast path, e^{-lambda r} / (4 pi r), same r = 0 story as eval_2d
+    pub fn eval_3d(&self, x: &[f64; 3], y: &[f64; 3]) -> f64 {
+        let r: f64 = distance(x, y);
+        assert_separated("ModifiedHelmholtz", r);
+        (-self.lambda * r).exp() / (4.0 * PI * r)
+    }
+}
+
+impl Kernel<2> for ModifiedHelmholtz {
+    type Scalar = f64;
+    fn decay_rate(&self) -> f64 { self.lambda }
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<f64> {
+        let laplace: f64 = laplace_self_2d(nodes, i)?;
+        if nodes.weight(i) == 0.0 { return Some(0.0) }
+        Some(laplace - ((self.lambda / 2.0).ln() + EULER_GAMMA) / (2.0 * PI))
+    }
+}
+
+impl PointKernel<2> for ModifiedHelmholtz {
+    fn eval( &self, x: &[f64; 2], y: &[f64; 2]) -> f64 { self.eval_2d(x, y) }
+}
+
+impl Kernel<3> for ModifiedHelmholtz {
+    type Scalar = f64;
+    fn decay_rate(&self) -> f64 { self.lambda }
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    // (e^{-lambda r} - 1) / (4 pi r) -> -lambda / 4pi, ie. the 1/(4 pi r) part is left to the caller's singular quadrature
+    fn eval_self(&self, _nodes: &Nodes<3>, _i: usize) -> Option<f64> { Some(-self.lambda / (4.0 * PI)) }
+}
+
+impl PointKernel<3> for ModifiedHelmholtz {
+    fn eval( &self, x: &[f64; 3], y: &[f64; 3]) -> f64 { self.eval_3d(x, y) }
+}
+
+// ------------------ COVARIANCE KERNELS ------------------
+
+// stationary covariances for Gaussian process regression, only depend on r so they work for any D
+// all take a length scale l and a variance sigma^2 (value at r = 0)
+
+// ----- Gaussian / RBF ---------
+
+// sigma^2 exp(-r^2 / 2l^2)
+pub struct Gaussian { pub length_scale: f64, pub variance: f64}
+
+impl Gaussian {
+    pub fn new(length_scale: f64, variance: f64) -> Self { Self {length_scale, variance}}
+
+    pub fn eval_real(&self, r: f64) -> f64 {
+        let s: f64 = r / self.length_scale;
+        self.variance * (-0.5 * s * s).exp()
+    }
+}
+
+impl<const D: usize> Kernel<D> for Gaussian {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+}
+
+impl<const D: usize> PointKernel<D> for Gaussian {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> f64 { self.eval_real(distance(x, y)) }
+}
+
+// ----- Matern ---------
+
+// sigma^2 2^{1-nu}/Gamma(nu) (sqrt(2nu) r/l)^nu K_nu(sqrt(2nu) r/l)
+// nu = 1/2 (exponential), 3/2 and 5/2 have closed forms, anything else goes through Bessel K
+pub struct Matern { pub nu: f64, pub length_scale: f64, pub variance: f64}
+
+impl Matern {
+    pub fn new(nu: f64, length_scale: f64, variance: f64) -> Self {
+        assert!(nu > 0.0, "Matern smoothness must be positive");
+        Self {nu, length_scale, variance}
+    }
+
+    // nu = 1/2, ie. sigma^2 exp(-r/l)
+    pub fn exponential(length_scale: f64, variance: f64) -> Self { Self::new(0.5, length_scale, variance)}
+
+    pub fn eval_real(&self, r: f64) -> f64 {
+        let s: f64 = r / self.length_scale;
+        if self.nu == 0.5 { return self.variance * (-s).exp() }
+        if self.nu == 1.5 {
+            let t: f64 = 3.0_f64.sqrt() * s;
+            return self.variance * (1.0 + t) * (-t).exp()
+        }
+        if self.nu == 2.5 {
+            let t: f64 = 5.0_f64.sqrt() * s;
+            return self.variance * (1.0 + t + t * t / 3.0) * (-t).exp()
+        }
+        self.eval_general(r)
+    }
+
+    // Bessel K form for any nu, what the closed forms above reduce to
+    pub fn eval_general(&self, r: f64) -> f64 {
+        let t: f64 = (2.0 * self.nu).sqrt() * r / self.length_scale;
+        if t == 0.0 { return self.variance } // t^nu K_nu(t) -> 2^{nu-1} Gamma(nu)
+        self.variance * 2.0_f64.powf(1.0 - self.nu) / gamma(self.nu) * t.powf(self.nu) * bessel_k(self.nu, t)
+    }
+}
+
+impl<const D: usize> Kernel<D> for Matern {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+}
+
+impl<const D: usize> PointKernel<D> for Matern {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> f64 { self.eval_real(distance(x, y)) }
+}
+
+// ----- Rational quadratic ---------
+
+// sigma^2 (1 + r^2 / 2 alpha l^2)^{-alpha}, a scale mixture of Gaussians -> Gaussian as alpha -> infinity
+pub struct RationalQuadratic { pub alpha: f64, pub length_scale: f64, pub variance: f64}
+
+impl RationalQuadratic {
+    pub fn new(alpha: f64, length_scale: f64, variance: f64) -> Self { Self {alpha, length_scale, variance}}
+
+    pub fn eval_real(&self, r: f64) -> f64 {
+        let s: f64 = r / self.length_scale;
+        self.variance * (1.0 + s * s / (2.0 * self.alpha)).powf(-self.alpha)
+    }
+}
+
+impl<const D: usize> Kernel<D> for RationalQuadratic {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+}
+
+impl<const D: usize> PointKernel<D> for RationalQuadratic {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> f64 { self.eval_real(distance(x, y)) }
+}
+
+// ------- Normal Derivatives (layer potentials) ----------
+
+// every kernel below is the single layer G(r) differentiated along normals, so all each one has to
+// supply is G'(r) and G''(r) -- the dot products with the normals are shared in layer_eval
+
+// difference x - y and its length, shared by the normal derivative kernels
+pub(crate) fn separation<const D: usize>(x: &[f64; D], y: &[f64; D]) -> ([f64; D], f64) {
+    let mut diff: [f64; D] = [0.0; D];
+    for d in 0..D { diff[d] = x[d] - y[d]; }
+    let r: f64 = diff.iter().map(|c| c * c).sum::<f64>().sqrt();
+    (diff, r)
+}
+
+pub(crate) fn dot<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    a.iter().zip(b).map(|(p, q)| p * q).sum()
+}
+
+// G'(r), G''(r) for each single layer
+fn laplace_radial_2d(r: f64) -> (f64, f64) {
+    (-1.0 / (2.0 * PI * r), 1.0 / (2.0 * PI * r * r))
+}
+
+fn laplace_radial_3d(r: f64) -> (f64, f64) {
+    (-1.0 / (4.0 * PI * r * r), 1.0 / (2.0 * PI * r * r * r))
+}
+
+// G = (i/4) H0(kr) -> G' = -(ik/4) H1(kr), G'' = -(ik^2/4) (H0(kr) - H1(kr)/kr)
+fn helmholtz_radial_2d(k: Complex64, r: f64) -> (Complex64, Complex64) {
+    let kr: Complex64 = k * r;
+    let (h0, h1): (Complex64, Complex64) = hankel1_01_complex(kr);
+    let ik: Complex64 = Complex64::i() * k;
+    (-ik * 0.25 * h1, -ik * k * 0.25 * (h0 - h1 / kr))
+}
+
+// G = e^{ikr}/(4 pi r) -> G' = e^{ikr}(ikr - 1)/(4 pi r^2), G'' = e^{ikr}(2 - 2ikr - k^2 r^2)/(4 pi r^3)
+fn helmholtz_radial_3d(k: Complex64, r: f64) -> (Complex64, Complex64) {
+    let ikr: Complex64 = Complex64::i() * k * r;
+    let e: Complex64 = ikr.exp() / (4.0 * PI * r * r);
+    (e * (ikr - 1.0), e * (2.0 - 2.0 * ikr + ikr * ikr) / r)
+}
+
+#[derive(Clone, Copy)]
+enum Layer {
+    Double, // dG/dn_y
+    AdjointDouble, // dG/dn_x
+    Hypersingular, // d2G/dn_x dn_y
+}
+
+// with d = x - y: dr/dn_y = -d.n_y / r and dr/dn_x = d.n_x / r, the rest is chain rule
+// coincident points return 0 -- the 2D double layers check eval_self first, everything else leaves it to the caller
+fn layer_eval<const D: usize, T: Scalar>(layer: Layer, name: &str, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize,
+    radial: impl Fn(f64) -> (T, T)) -> T {
+
+    let normal_x = || targets.normal(i).unwrap_or_else(|| panic!("{} needs normals on the target Nodes", name));
+    let normal_y = || sources.normal(j).unwrap_or_else(|| panic!("{} needs normals on the source Nodes", name));
+
+    let (diff, r) = separation(&targets.points[i], &sources.points[j]);
+    if r == 0.0 { return T::zero() }
+    let (g1, g2) = radial(r);
+
+    match layer {
+        Layer::Double => -g1 * T::from_real(dot(&diff, normal_y()) / r),
+        Layer::AdjointDouble => g1 * T::from_real(dot(&diff, normal_x()) / r),
+        Layer::Hypersingular => {
+            let (nx, ny) = (normal_x(), normal_y());
+            let (dnx, dny) = (dot(&diff, nx), dot(&diff, ny));
+            -g2 * T::from_real(dnx * dny / (r * r)) - g1 * T::from_real(dot(nx, ny) / r - dnx * dny / (r * r * r))
+        }
+    }
+}
+
+// 2D double layer diagonal. on a counter clockwise curve with outward normals x(s + h) - x(s) = h t - (h^2/2) kappa n + ...
+// so (x - y).n_y / r^2 -> -kappa / 2 and both dG/dn_y and dG/dn_x tend to -kappa / 4pi. the Helmholtz kernels only add
+// O(r log r) to the Laplace ones so they share the limit. the kernel is smooth, so with this the trapezoid rule stays spectral
+fn double_layer_self_2d(nodes: &Nodes<2>, i: usize) -> Option<f64> {
+    Some(-nodes.curvature(i)? / (4.0 * PI))
+}
+
+// n_x.n_y K(x, y), needs normals on both Nodes. with K = G this is S_n in the Maue form of the 2D hypersingular operator,
+// W phi = d/ds_x S[dphi/ds] + k^2 S_n[phi] on a closed curve: both kernels are only log singular, so they take the same
+// self terms / Kapur-Rokhlin corrections as S and the derivatives go outside (ArcDerivative in quadrature.rs)
+#[derive(Clone, Copy)]
+pub struct NormalProduct<K> { pub kernel: K }
+
+impl<K> NormalProduct<K> {
+    pub fn new(kernel: K) -> Self { Self {kernel}}
+}
+
+impl<const D: usize, K: ScalarKernel<D>> Kernel<D> for NormalProduct<K> {
+    type Scalar = K::Scalar;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        let nx: &[f64; D] = targets.normal(i).unwrap_or_else(|| panic!("NormalProduct needs normals on the target Nodes"));
+        let ny: &[f64; D] = sources.normal(j).unwrap_or_else(|| panic!("NormalProduct needs normals on the source Nodes"));
+        self.kernel.eval_nodes(targets, i, sources, j) * K::Scalar::from_real(dot(nx, ny))
+    }
+    // n.n = 1
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K::Scalar> { self.kernel.eval_self(nodes, i) }
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+}
+
+// ----- Laplace layers ---------
+
+// dG/dn_y, needs normals on the source Nodes
+pub struct LaplaceNormal;
+
+// dG/dn_x, needs normals on the target Nodes
+pub struct LaplaceAdjointNormal;
+
+// d2G/dn_x dn_y, needs normals on both
+pub struct LaplaceHypersingular;
+
+impl Kernel<2> for LaplaceNormal {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        coincident_self(self, targets, i, sources, j)
+            .unwrap_or_else(|| layer_eval(Layer::Double, "LaplaceNormal", targets, i, sources, j, laplace_radial_2d))
+    }
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<f64> { double_layer_self_2d(nodes, i) }
+}
+
+impl Kernel<3> for LaplaceNormal {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> f64 {
+        layer_eval(Layer::Double, "LaplaceNormal", targets, i, sources, j, laplace_radial_3d)
+    }
+}
+
+impl Kernel<2> for LaplaceAdjointNormal {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        coincident_self(self, targets, i, sources, j)
+            .unwrap_or_else(|| layer_eval(Layer::AdjointDouble, "LaplaceAdjointNormal", targets, i, sources, j, laplace_radial_2d))
+    }
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<f64> { double_layer_self_2d(nodes, i) }
+}
+
+impl Kernel<3> for LaplaceAdjointNormal {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> f64 {
+        layer_eval(Layer::AdjointDouble, "LaplaceAdjointNormal", targets, i, sources, j, laplace_radial_3d)
+    }
+}
+
+impl Kernel<2> for LaplaceHypersingular {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        layer_eval(Layer::Hypersingular, "LaplaceHypersingular", targets, i, sources, j, laplace_radial_2d)
+    }
+}
+
+impl Kernel<3> for LaplaceHypersingular {
+    type Scalar = f64;
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> f64 {
+        layer_eval(Layer::Hypersingular, "LaplaceHypersingular", targets, i, sources, j, laplace_radial_3d)
+    }
+}
+
+impl LaplaceHypersingular {
+    // kernel of S in the Maue form, k = 0 so the S_n half vanishes and W = d/ds S d/ds, see NormalProduct
+    pub fn maue(&self) -> Laplace { Laplace }
+}
+
+// ----- Helmholtz layers ---------
+
+// dG/dn_y, needs unit normals on the source Nodes -- eg. Nodes::with_normals or cardioid_boundary
+#[derive(Clone, Copy)]
+pub struct HelmholtzNormal { pub wavenumber: Complex64}
+
+// dG/dn_x, needs normals on the target Nodes
+#[derive(Clone, Copy)]
+pub struct HelmholtzAdjointNormal { pub wavenumber: Complex64}
+
+// d2G/dn_x dn_y, needs normals on both
+#[derive(Clone, Copy)]
+pub struct HelmholtzHypersingular { pub wavenumber: Complex64}
+
+// new methods for ease of setting k -- eg. HelmholtzNormal::new(3.02)
+impl HelmholtzNormal { pub fn new(wavenumber: impl Into<Complex64>) -> Self { Self {wavenumber: wavenumber.into()}}}
+impl HelmholtzAdjointNormal { pub fn new(wavenumber: impl Into<Complex64>) -> Self { Self {wavenumber: wavenumber.into()}}}
+impl HelmholtzHypersingular { pub fn new(wavenumber: impl Into<Complex64>) -> Self { Self {wavenumber: wavenumber.into()}}}
+
+// 2D: dG/dn_y = (ik/4) H1_1(kr) (x - y).n_y / r
+impl Kernel<2> for HelmholtzNormal {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        coincident_self(self, targets, i, sources, j)
+            .unwrap_or_else(|| layer_eval(Layer::Double, "HelmholtzNormal", targets, i, sources, j, |r| helmholtz_radial_2d(self.wavenumber, r)))
+    }
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> { double_layer_self_2d(nodes, i).map(Complex64::from) }
+}
+
+// 3D: dG/dn_y = e^{ikr} (1 - ikr) (x - y).n_y / (4 pi r^3)
+impl Kernel<3> for HelmholtzNormal {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        layer_eval(Layer::Double, "HelmholtzNormal", targets, i, sources, j, |r| helmholtz_radial_3d(self.wavenumber, r))
+    }
+}
+
+impl Kernel<2> for HelmholtzAdjointNormal {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        coincident_self(self, targets, i, sources, j)
+            .unwrap_or_else(|| layer_eval(Layer::AdjointDouble, "HelmholtzAdjointNormal", targets, i, sources, j, |r| helmholtz_radial_2d(self.wavenumber, r)))
+    }
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> { double_layer_self_2d(nodes, i).map(Complex64::from) }
+}
+
+impl Kernel<3> for HelmholtzAdjointNormal {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        layer_eval(Layer::AdjointDouble, "HelmholtzAdjointNormal", targets, i, sources, j, |r| helmholtz_radial_3d(self.wavenumber, r))
+    }
+}
+
+impl Kernel<2> for HelmholtzHypersingular {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        layer_eval(Layer::Hypersingular, "HelmholtzHypersingular", targets, i, sources, j, |r| helmholtz_radial_2d(self.wavenumber, r))
+    }
+}
+
+impl Kernel<3> for HelmholtzHypersingular {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        layer_eval(Layer::Hypersingular, "HelmholtzHypersingular", targets, i, sources, j, |r| helmholtz_radial_3d(self.wavenumber, r))
+    }
+}
+
+impl HelmholtzHypersingular {
+    // kernels of S and k^2 S_n in the Maue form W = d/ds S d/ds + k^2 S_n, see NormalProduct
+    pub fn maue(&self) -> (Helmholtz, Scaled<NormalProduct<Helmholtz>, Complex64>) {
+        let single: Helmholtz = Helmholtz::new(self.wavenumber);
+        (single, Scaled::new(NormalProduct::new(single), self.wavenumber * self.wavenumber))
+    }
+}
+
+// ----- Combined field (Brakhage-Werner) ---------
+
+// D - i eta S in one kernel so a single HMatrix holds the whole exterior Dirichlet operator
+// the single layer term kills the interior resonances that D alone has, eta = |k| is the usual choice
+#[derive(Clone, Copy)]
+pub struct CombinedField { pub wavenumber: Complex64, pub eta: f64}
+
+impl CombinedField {
+    pub fn new(wavenumber: impl Into<Complex64>) -> Self {
+        let wavenumber: Complex64 = wavenumber.into();
+        Self {wavenumber, eta: wavenumber.norm()}
+    }
+    pub fn with_coupling(wavenumber: impl Into<Complex64>, eta: f64) -> Self { Self {wavenumber: wavenumber.into(), eta}}
+}
+
+impl Kernel<2> for CombinedField {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        let double: Complex64 = HelmholtzNormal::new(self.wavenumber).eval_nodes(targets, i, sources, j);
+        let single: Complex64 = Helmholtz::new(self.wavenumber).eval_nodes(targets, i, sources, j);
+        double - Complex64::i() * self.eta * single
+    }
+    // needs both halves: curvature for D and weights for S
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> {
+        let double: Complex64 = HelmholtzNormal::new(self.wavenumber).eval_self(nodes, i)?;
+        let single: Complex64 = Helmholtz::new(self.wavenumber).eval_self(nodes, i)?;
+        Some(double - Complex64::i() * self.eta * single)
+    }
+}
+
+impl Kernel<3> for CombinedField {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+    fn eval_nodes(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        let double: Complex64 = HelmholtzNormal::new(self.wavenumber).eval_nodes(targets, i, sources, j);
+        let single: Complex64 = Helmholtz::new(self.wavenumber).eval_nodes(targets, i, sources, j);
+        double - Complex64::i() * self.eta * single
+    }
+}
+
+// ------------------ QUASI-PERIODIC HELMHOLTZ ------------------
+
+// 2D Helmholtz Green's function of a grating: images of the source at y + n d e_1 with Bloch phase e^{i alpha n d},
+// G(x, y) = sum_n e^{i alpha n d} i/4 H0(k |x - y - n d e_1|), so G(x + d e_1, y) = e^{i alpha d} G(x, y)
+// the image sum barely converges (~ n^{-1/2} oscillating) so evaluate it by Ewald splitting (Linton 1998):
+// a spectral sum over the Bloch modes beta_m = alpha + 2 pi m / d plus a spatial sum over the few images near x - y,
+// both Gaussian-damped. blows up at Wood anomalies (some |beta_m| = k) which new refuses
+#[derive(Clone, Copy)]
+pub struct QuasiPeriodicHelmholtz { pub wavenumber: Complex64, pub period: f64, pub bloch: f64 }
+
+impl QuasiPeriodicHelmholtz {
+    pub fn new(wavenumber: impl Into<Complex64>, period: f64, bloch: f64) -> Self {
+        let wavenumber: Complex64 = wavenumber.into();
+        assert!(period > 0.0, "period must be positive, got {}", period);
+        let kernel: Self = Self {wavenumber, period, bloch};
+        let m_max: i64 = (wavenumber.norm() * period / (2.0 * PI)) as i64 + 2;
+        for m in -m_max - 1..=m_max + 1 {
+            let gamma: Complex64 = kernel.mode_gamma(m);
+            assert!(gamma.norm() > 1e-8 * wavenumber.norm().max(1.0), "Wood anomaly: beta_{} = alpha + 2 pi m / d hits k", m);
+        }
+        kernel
+    }
+
+    // splitting parameter, sqrt(pi)/d is the usual choice -- pushed up at large k so (k/2E)^2 stays small,
+    // otherwise the spectral and spatial halves cancel and take digits with them
+    fn ewald_parameter(&self) -> f64 {
+        (PI.sqrt() / self.period).max(self.wavenumber.norm() / (2.0 * 3f64.sqrt()))
+    }
+
+    // gamma_m = sqrt(beta_m^2 - k^2) on the outgoing / decaying branch, -i sqrt(k^2 - beta_m^2) with the principal root
+    fn mode_gamma(&self, m: i64) -> Complex64 {
+        let beta: f64 = self.bloch + 2.0 * PI * m as f64 / self.period;
+        -Complex64::i() * (self.wavenumber * self.wavenumber - beta * beta).sqrt()
+    }
+
+    // e^{i beta_m X} / gamma_m sum_+- e^{+-gamma Y} erfc(gamma/2E +- Y E), the e^{+-gamma Y} and e^{-z^2} in erfc
+    // combine into one e^{-gamma^2/4E^2 - Y^2 E^2} so nothing overflows at large |Y| or large m
+    fn spectral_mode(&self, m: i64, x: f64, y: f64, e: f64) -> Complex64 {
+        let beta: f64 = self.bloch + 2.0 * PI * m as f64 / self.period;
+        let gamma: Complex64 = self.mode_gamma(m);
+        let damp: Complex64 = (-gamma * gamma / (4.0 * e * e) - y * y * e * e).exp();
+        let mut total: Complex64 = Complex64::new(0.0, 0.0);
+        for sign in [1.0, -1.0] {
+            let z: Complex64 = gamma / (2.0 * e) + sign * y * e;
+            total += if z.re >= 0.0 {
+                damp * faddeeva(Complex64::i() * z)
+            } else {
+                2.0 * (sign * gamma * y).exp() - damp * faddeeva(-Complex64::i() * z)
+            };
+        }
+        Complex64::from_polar(1.0, beta * x) * total / gamma
+    }
+
+    fn spectral_sum(&self, x: f64, y: f64, e: f64) -> Complex64 {
+        // start from the mode closest to normal incidence and walk out until both sides are below round off
+        let centre: i64 = (-self.bloch * self.period / (2.0 * PI)).round() as i64;
+        let propagating: i64 = (self.wavenumber.norm() * self.period / (2.0 * PI)) as i64 + 1;
+        let mut total: Complex64 = self.spectral_mode(centre, x, y, e);
+        for j in 1..MAX_MODES {
+            let pair: Complex64 = self.spectral_mode(centre + j, x, y, e) + self.spectral_mode(centre - j, x, y, e);
+            total += pair;
+            if j > propagating && pair.norm() < 1e-17 * total.norm() { break }
+        }
+        total / (4.0 * self.period)
+    }
+
+    // sum_q (k/2E)^{2q} / q! E_{q+1}(r^2 E^2), times 4 pi this is the Gaussian-damped free space kernel of one image
+    fn spatial_term(&self, r2: f64, e: f64) -> Complex64 {
+        let c: Complex64 = (self.wavenumber / (2.0 * e)).powi(2);
+        let mut power: Complex64 = Complex64::new(1.0, 0.0);
+        let mut total: Complex64 = Complex64::new(0.0, 0.0);
+        for q in 0..MAX_MODES as usize {
+            let term: Complex64 = power * exp_integral(q + 1, r2 * e * e);
+            total += term;
+            if q as f64 > c.norm() && term.norm() < 1e-17 * total.norm() { break }
+            power *= c / (q + 1) as f64;
+        }
+        total
+    }
+
+    // images n with (X - n d)^2 + Y^2 past 40 / E^2 are below e^{-40}, X is already reduced into [-d/2, d/2]
+    // skip_centre leaves out n = 0 for the self term
+    fn spatial_sum(&self, x: f64, y: f64, e: f64, skip_centre: bool) -> Complex64 {
+        let mut total: Complex64 = Complex64::new(0.0, 0.0);
+        for side in [1.0, -1.0] {
+            for n in (if side > 0.0 { 0 } else { 1 })..MAX_MODES {
+                let shift: f64 = side * n as f64 * self.period;
+                let r2: f64 = (x - shift).powi(2) + y * y;
+                if n > 0 && r2 * e * e > 40.0 { break }
+                if n == 0 && skip_centre { continue }
+                total += Complex64::from_polar(1.0, self.bloch * shift) * self.spatial_term(r2, e);
+            }
+        }
+        total / (4.0 * PI)
+    }
+
+    // G at separation (X, Y) = x - y
+    fn eval_separation(&self, sx: f64, sy: f64) -> Complex64 {
+        // Bloch reduce X into [-d/2, d/2] so the spatial sum stays centred on the nearest images
+        let cell: f64 = (sx / self.period).round();
+        let x: f64 = sx - cell * self.period;
+        assert_separated("QuasiPeriodicHelmholtz", x.hypot(sy)); // target on a periodic image of the source
+        let e: f64 = self.ewald_parameter();
+        Complex64::from_polar(1.0, self.bloch * cell * self.period) * (self.spectral_sum(x, sy, e) + self.spatial_sum(x, sy, e, false))
+    }
+
+    // G + log(r) / 2pi at r -> 0, the n = 0 image's E_1(r^2 E^2) supplies the log and
+    // E_1(x) = -gamma - log x + O(x), E_{q+1}(0) = 1/q give the rest
+    pub fn self_constant(&self) -> Complex64 {
+        let e: f64 = self.ewald_parameter();
+        let c: Complex64 = (self.wavenumber / (2.0 * e)).powi(2);
+        let mut series: Complex64 = Complex64::new(0.0, 0.0);
+        let mut power: Complex64 = Complex64::new(1.0, 0.0);
+        for q in 1..MAX_MODES as usize {
+            power *= c / q as f64;
+            let term: Complex64 = power / q as f64;
+            series += term;
+            if q as f64 > c.norm() && term.norm() < 1e-17 * series.norm().max(1.0) { break }
+        }
+        let centre: Complex64 = (-EULER_GAMMA - 2.0 * e.ln() + series) / (4.0 * PI);
+        self.spectral_sum(0.0, 0.0, e) + self.spatial_sum(0.0, 0.0, e, true) + centre
+    }
+}
+
+const MAX_MODES: i64 = 10_000;
+
+impl Kernel<2> for QuasiPeriodicHelmholtz {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    // same Nystrom diagonal as Helmholtz, with the images and the Ewald bits folded into self_constant
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> {
+        let laplace: f64 = laplace_self_2d(nodes, i)?;
+        if nodes.weight(i) == 0.0 { return Some(Complex64::new(0.0, 0.0)) }
+        Some(laplace + self.self_constant())
+    }
+}
+
+impl PointKernel<2> for QuasiPeriodicHelmholtz {
+    fn eval( &self, x: &[f64; 2], y: &[f64; 2]) -> Complex64 {
+        self.eval_separation(x[0] - y[0], x[1] - y[1])
+    }
+}
+
+// ------------------ HALF-SPACE (IMAGE METHOD) ------------------
+
+// what the plane does to the field, Dirichlet: u = 0 (soft / grounded), Neumann: du/dn = 0 (rigid ground)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary { Dirichlet, Neumann }
+
+// free space kernel plus or minus its mirror image in the plane n.x = offset: G(x, y) -+ G(x, y*),
+// y* = y - 2 (n.y - offset) n, minus for Dirichlet, plus for Neumann. the image needs G at a point that isn't a
+// node, so only point kernels go in (Laplace, Helmholtz, ModifiedHelmholtz, ...) -- eg. HalfSpace::new(Helmholtz::new(3.0), Boundary::Neumann) for rigid ground at z = 0
+// sources and targets should sit on the n side of the plane (or on it), and Weighted goes outside: Weighted::new(HalfSpace::new(..))
+#[derive(Clone, Copy)]
+pub struct HalfSpace<K, const D: usize> { pub kernel: K, pub boundary: Boundary, pub normal: [f64; D], pub offset: f64 }
+
+impl<K, const D: usize> HalfSpace<K, D> {
+    // plane x_D = 0 (y = 0 in 2D, z = 0 in 3D), domain above it
+    pub fn new(kernel: K, boundary: Boundary) -> Self {
+        let mut normal: [f64; D] = [0.0; D];
+        normal[D - 1] = 1.0;
+        Self {kernel, boundary, normal, offset: 0.0}
+    }
+
+    // plane n.x = offset for any orientation, n gets normalised
+    pub fn with_plane(kernel: K, boundary: Boundary, normal: [f64; D], offset: f64) -> Self {
+        let length: f64 = dot(&normal, &normal).sqrt();
+        assert!(length > 0.0, "plane normal can't be zero");
+        let (normal, offset): ([f64; D], f64) = (normal.map(|n| n / length), offset / length);
+        Self {kernel, boundary, normal, offset}
+    }
+
+    pub fn mirror(&self, y: &[f64; D]) -> [f64; D] {
+        let height: f64 = dot(&self.normal, y) - self.offset;
+        let mut image: [f64; D] = *y;
+        for (value, n) in image.iter_mut().zip(&self.normal) { *value -= 2.0 * height * n; }
+        image
+    }
+
+    fn image_sign(&self) -> f64 {
+        match self.boundary { Boundary::Dirichlet => -1.0, Boundary::Neumann => 1.0 }
+    }
+}
+
+impl<const D: usize, K: PointKernel<D> + ScalarKernel<D>> HalfSpace<K, D> {
+    // image term, a source on the plane is its own image so it just repeats the direct entry (self term included)
+    fn image(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize, direct: K::Scalar) -> K::Scalar {
+        let image: [f64; D] = self.mirror(&sources.points[j]);
+        if is_diagonal(targets, i, sources, j) && image == sources.points[j] { return direct }
+        self.kernel.eval(&targets.points[i], &image)
+    }
+}
+
+impl<const D: usize, K: PointKernel<D> + ScalarKernel<D>> Kernel<D> for HalfSpace<K, D> {
+    type Scalar = K::Scalar;
+
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        let direct: K::Scalar = self.kernel.eval_nodes(targets, i, sources, j);
+        direct + K::Scalar::from_real(self.image_sign()) * self.image(targets, i, sources, j, direct)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<D>, i: usize) -> Option<K::Scalar> {
+        let direct: K::Scalar = self.kernel.eval_self(nodes, i)?;
+        Some(direct + K::Scalar::from_real(self.image_sign()) * self.image(nodes, i, nodes, i, direct))
+    }
+
+    // both halves through the wrapped kernel's block path, the images as a mirrored copy of the source columns.
+    // an on plane source is its own image, and the mirrored copy isn't the same Nodes, so a diagonal block with one
+    // goes entry by entry through eval_nodes to pick the self term up in both halves
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [K::Scalar]) {
+        let images: Vec<[f64; D]> = cols.iter().map(|&j| self.mirror(&sources.points[j])).collect();
+        if std::ptr::eq(targets, sources) && cols.iter().zip(&images).any(|(&j, image)| *image == sources.points[j]) {
+            assert_eq!(out.len(), rows.len() * cols.len(), "out must be len(rows) x len(cols)");
+            let n: usize = cols.len();
+            for (a, &i) in rows.iter().enumerate() {
+                for (b, &j) in cols.iter().enumerate() { out[a * n + b] = self.eval_nodes(targets, i, sources, j); }
+            }
+            return
+        }
+        self.kernel.eval_block(targets, rows, sources, cols, out);
+        let mirrored: Nodes<D> = Nodes::new(images);
+        let mut image: Vec<K::Scalar> = vec![K::Scalar::zero(); out.len()];
+        self.kernel.eval_block(targets, rows, &mirrored, &(0..cols.len()).collect::<Vec<usize>>(), &mut image);
+        let sign: K::Scalar = K::Scalar::from_real(self.image_sign());
+        for (value, extra) in out.iter_mut().zip(image) { *value += sign * extra; }
+    }
+
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+}
+
+impl<const D: usize, K: PointKernel<D> + ScalarKernel<D>> PointKernel<D> for HalfSpace<K, D> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> K::Scalar {
+        self.kernel.eval(x, y) + K::Scalar::from_real(self.image_sign()) * self.kernel.eval(x, &self.mirror(y))
+    }
+}
+
+// ------------------ AXISYMMETRIC (BODIES OF REVOLUTION) ------------------
+
+// for a body of revolution and data going like e^{im theta} around the axis, the 3D single layer reduces to one on the
+// generating curve in the (rho, z) half plane, rho >= 0 the distance from the axis. with x = [rho, z], y = [rho', z']
+// G_m(x, y) = rho' int_0^{2 pi} G(R(phi)) cos(m phi) dphi, R^2 = (rho - rho')^2 + (z - z')^2 + 4 rho rho' sin^2(phi / 2)
+// the rho' is the source ring's circumference so arclength weights along the curve (eg. cardioid_boundary) integrate it
+// directly. modes m and -m share a kernel. on the axis: a source there is a ring of length 0 so G_m = 0 for every m,
+// a target there sees the whole ring at one distance so only m = 0 survives
+
+// 1/(4 pi R) integrates in closed form, G_m = rho' Q_{m-1/2}(chi) / (2 pi sqrt(rho rho')) with the toroidal harmonic
+// at chi = 1 + ((rho - rho')^2 + (z - z')^2) / (2 rho rho') -- m = 0 is the usual complete elliptic integral ring
+#[derive(Clone, Copy)]
+pub struct AxisymmetricLaplace { pub mode: usize }
+
+impl AxisymmetricLaplace { pub fn new(mode: usize) -> Self { Self {mode}}}
+
+// squared meridian distance, None where the ring kernels vanish (source ring on the axis) or blow up (coincident)
+fn ring_separation(x: &[f64; 2], y: &[f64; 2]) -> Option<f64> {
+    assert!(x[0] >= 0.0 && y[0] >= 0.0, "axisymmetric points are [rho, z] with rho >= 0, got {:?} and {:?}", x, y);
+    let d2: f64 = (x[0] - y[0]).powi(2) + (x[1] - y[1]).powi(2);
+    if y[0] == 0.0 || d2 == 0.0 { None } else { Some(d2) }
+}
+
+// Q_{m-1/2}(chi) ~ log(8 rho / d) - sum_{l=1}^m 2/(2l - 1) as chi -> 1, so G_m is the 2D Laplace -log(d) / 2pi plus a part
+// tending to (log(8 rho) - sum) / 2pi. with trapezoid weights the first half is laplace_self_2d. a node on the axis is a
+// ring of length 0 and gets 0 like every other entry in its column
+fn axisymmetric_laplace_self(mode: usize, nodes: &Nodes<2>, i: usize) -> Option<f64> {
+    let laplace: f64 = laplace_self_2d(nodes, i)?;
+    let rho: f64 = nodes.points[i][0];
+    if rho == 0.0 || nodes.weight(i) == 0.0 { return Some(0.0) }
+    let harmonic: f64 = (1..=mode).map(|l| 2.0 / (2 * l - 1) as f64).sum();
+    Some(laplace + ((8.0 * rho).ln() - harmonic) / (2.0 * PI))
+}
+
+impl Kernel<2> for AxisymmetricLaplace {
+    type Scalar = f64;
+
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> f64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<f64> { axisymmetric_laplace_self(self.mode, nodes, i) }
+}
+
+impl PointKernel<2> for AxisymmetricLaplace {
+    fn eval(&self, x: &[f64; 2], y: &[f64; 2]) -> f64 {
+        let Some(d2) = ring_separation(x, y) else { return 0.0 };
+        let (rho, rho_s): (f64, f64) = (x[0], y[0]);
+        if rho == 0.0 { return if self.mode == 0 { rho_s / (2.0 * d2.sqrt()) } else { 0.0 } }
+        rho_s * toroidal_q(self.mode, d2 / (2.0 * rho * rho_s)) / (2.0 * PI * (rho * rho_s).sqrt())
+    }
+}
+
+// azimuthal integral by 16 point Gauss-Legendre panels on [0, pi] (the integrand is even in phi). e^{ikR} / R is analytic
+// there apart from the branch points of R at phi ~ +-i eta, eta = sqrt(d^2 / rho rho'), so panels double in length from
+// eta outwards and are then cut to about one period of cos(m phi) e^{ikR}. that is 16 (log2(pi / eta) + m + |k| (rho + rho') / 2)
+// exponentials or so per entry, ~1000 for nodes 1e-12 apart. close pairs (chi < 2) split off the Laplace ring above and
+// only integrate the bounded (e^{ikR} - 1) / (4 pi R), far ones don't since at high m the two halves cancel
+#[derive(Clone, Copy)]
+pub struct AxisymmetricHelmholtz { pub wavenumber: Complex64, pub mode: usize }
+
+impl AxisymmetricHelmholtz {
+    pub fn new(wavenumber: impl Into<Complex64>, mode: usize) -> Self { Self {wavenumber: wavenumber.into(), mode}}
+
+    // rho' int_0^{2 pi} (e^{ikR} [- 1]) / (4 pi R) cos(m phi) dphi, see above. d2 = 0 is fine with the - 1 (the self term)
+    fn ring_integral(&self, d2: f64, rho: f64, rho_s: f64, split: bool) -> Complex64 {
+        let k: Complex64 = self.wavenumber;
+        let integrand = |phi: f64| -> Complex64 {
+            let r: f64 = (d2 + 4.0 * rho * rho_s * (0.5 * phi).sin().powi(2)).sqrt();
+            // e^{ikR} - 1 = 2i e^{ikR/2} sin(kR/2) keeps its digits for small kR
+            let half: Complex64 = 0.5 * k * r;
+            let wave: Complex64 = if split { 2.0 * Complex64::i() * (Complex64::i() * half).exp() * half.sin() } else { (Complex64::i() * k * r).exp() };
+            wave / (4.0 * PI * r) * (self.mode as f64 * phi).cos()
+        };
+
+        // dyadic grading towards phi = 0, none needed if nothing is nearly singular (d2 = 0 has no 1/R left after the split)
+        let eta: f64 = if d2 == 0.0 { PI } else { (d2 / (rho * rho_s)).sqrt().min(PI) };
+        let mut breaks: Vec<f64> = vec![0.0];
+        let mut b: f64 = eta;
+        while b < PI { breaks.push(b); b *= 2.0; }
+        breaks.push(PI);
+
+        let longest: f64 = 2.0 * PI / (1.0 + self.mode as f64 + 0.5 * k.norm() * (rho + rho_s));
+        let mut total: Complex64 = Complex64::new(0.0, 0.0);
+        for pair in breaks.windows(2) {
+            let pieces: usize = ((pair[1] - pair[0]) / longest).ceil() as usize;
+            let h: f64 = (pair[1] - pair[0]) / pieces as f64;
+            for p in 0..pieces {
+                let a: f64 = pair[0] + h * p as f64;
+                total += gauss_legendre_16(a, a + h, integrand);
+            }
+        }
+        2.0 * rho_s * total
+    }
+}
+
+impl Kernel<2> for AxisymmetricHelmholtz {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+
+    fn eval_nodes(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        point_eval_nodes(self, targets, i, sources, j)
+    }
+
+    // the Laplace ring's self term plus the bounded remainder integrated at d = 0
+    fn eval_self(&self, nodes: &Nodes<2>, i: usize) -> Option<Complex64> {
+        let laplace: f64 = axisymmetric_laplace_self(self.mode, nodes, i)?;
+        let rho: f64 = nodes.points[i][0];
+        if rho == 0.0 || nodes.weight(i) == 0.0 { return Some(Complex64::new(0.0, 0.0)) }
+        Some(laplace + self.ring_integral(0.0, rho, rho, true))
+    }
+}
+
+impl PointKernel<2> for AxisymmetricHelmholtz {
+    fn eval(&self, x: &[f64; 2], y: &[f64; 2]) -> Complex64 {
+        let Some(d2) = ring_separation(x, y) else { return Complex64::new(0.0, 0.0) };
+        let (rho, rho_s): (f64, f64) = (x[0], y[0]);
+        let k: Complex64 = self.wavenumber;
+        if rho == 0.0 {
+            let r: f64 = d2.sqrt();
+            return if self.mode == 0 { rho_s * (Complex64::i() * k * r).exp() / (2.0 * r) } else { Complex64::new(0.0, 0.0) }
+        }
+
+        let split: bool = d2 / (2.0 * rho * rho_s) < 1.0;
+        let laplace: f64 = if split { AxisymmetricLaplace::new(self.mode).eval(x, y) } else { 0.0 };
+        laplace + self.ring_integral(d2, rho, rho_s, split)
+    }
+}
+
+// ------------------ SCALAR KERNEL MARKERS ------------------
+
+// wrappers are scalar exactly when what they wrap is, Tensor<K> is the one Kernel that isn't
+impl<const D: usize> ScalarKernel<D> for Gaussian {}
+impl<const D: usize> ScalarKernel<D> for Matern {}
+impl<const D: usize> ScalarKernel<D> for RationalQuadratic {}
+impl<const D: usize, T: Scalar, F: Fn(&[f64; D], &[f64; D]) -> T> ScalarKernel<D> for FnKernel<F> {}
+impl<const D: usize, K: ScalarKernel<D>, F: Fn(&Nodes<D>, usize) -> K::Scalar> ScalarKernel<D> for SelfTerm<K, F> {}
+impl<const D: usize, K: ScalarKernel<D>> ScalarKernel<D> for Weighted<K> {}
+impl<const D: usize, K: ScalarKernel<D>> ScalarKernel<D> for Scaled<K, f64> {}
+impl<const D: usize, K: ScalarKernel<D, Scalar = Complex64>> ScalarKernel<D> for Scaled<K, Complex64> {}
+impl<const D: usize, K1: ScalarKernel<D>, K2: ScalarKernel<D, Scalar = K1::Scalar>> ScalarKernel<D> for Sum<K1, K2> {}
+impl<const D: usize, K: PointKernel<D> + ScalarKernel<D>> ScalarKernel<D> for HalfSpace<K, D> {}
+impl<const D: usize, K: KernelDk<D> + ScalarKernel<D>> ScalarKernel<D> for Dk<K> {}
+impl ScalarKernel<2> for Laplace {}
+impl ScalarKernel<3> for Laplace {}
+impl ScalarKernel<2> for Helmholtz {}
+impl ScalarKernel<3> for Helmholtz {}
+impl ScalarKernel<2> for ModifiedHelmholtz {}
+impl ScalarKernel<3> for ModifiedHelmholtz {}
+impl ScalarKernel<2> for LaplaceNormal {}
+impl ScalarKernel<3> for LaplaceNormal {}
+impl ScalarKernel<2> for LaplaceAdjointNormal {}
+impl ScalarKernel<3> for LaplaceAdjointNormal {}
+impl ScalarKernel<2> for LaplaceHypersingular {}
+impl ScalarKernel<3> for LaplaceHypersingular {}
+impl ScalarKernel<2> for HelmholtzNormal {}
+impl ScalarKernel<3> for HelmholtzNormal {}
+impl ScalarKernel<2> for HelmholtzAdjointNormal {}
+impl ScalarKernel<3> for HelmholtzAdjointNormal {}
+impl ScalarKernel<2> for HelmholtzHypersingular {}
+impl ScalarKernel<3> for HelmholtzHypersingular {}
+impl ScalarKernel<2> for CombinedField {}
+impl<const D: usize, K: ScalarKernel<D>> ScalarKernel<D> for NormalProduct<K> {}
+impl ScalarKernel<3> for CombinedField {}
+impl ScalarKernel<2> for QuasiPeriodicHelmholtz {}
+impl ScalarKernel<2> for AxisymmetricLaplace {}
+impl ScalarKernel<2> for AxisymmetricHelmholtz {}
+
+// ------------------ WAVENUMBER DERIVATIVES ------------------
+
+// dK/dk for kernels with a wavenumber, what Newton in k needs when refining resonances / billiard eigenvalues
+// assemble it through Dk (or HMatrix::assemble_dk to reuse an existing BlockTree)
+pub trait KernelDk<const D: usize>: Kernel<D> {
+    // as eval_nodes, layer kernels pick up normals here
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> Self::Scalar;
+}
+
+// dG/dk from the two positions alone, the PointKernel counterpart
+pub trait PointKernelDk<const D: usize>: KernelDk<D> + PointKernel<D> {
+    fn eval_dk(&self, x: &[f64; D], y: &[f64; D]) -> Self::Scalar;
+}
+
+// the derivative as a kernel in its own right, eg. HMatrix::assemble(.., Dk::new(Helmholtz::new(3.0)))
+#[derive(Clone, Copy)]
+pub struct Dk<K> { pub kernel: K }
+
+impl<K> Dk<K> {
+    pub fn new(kernel: K) -> Self { Self {kernel}}
+}
+
+impl<const D: usize, K: KernelDk<D>> Kernel<D> for Dk<K> {
+    type Scalar = K::Scalar;
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes_dk(targets, i, sources, j)
+    }
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+    fn components(&self) -> usize { self.kernel.components() }
+}
+
+impl<const D: usize, K: PointKernelDk<D>> PointKernel<D> for Dk<K> {
+    fn eval( &self, x: &[f64; D], y: &[f64; D]) -> K::Scalar { self.kernel.eval_dk(x, y) }
+}
+
+impl<const D: usize, K: KernelDk<D>> KernelDk<D> for Weighted<K> {
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes_dk(targets, i, sources, j) * K::Scalar::from_real(sources.weight(j / self.components()))
+    }
+}
+
+impl<const D: usize, K: KernelDk<D>> KernelDk<D> for Scaled<K, f64> {
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes_dk(targets, i, sources, j) * K::Scalar::from_real(self.factor)
+    }
+}
+
+impl<const D: usize, K: PointKernelDk<D>> PointKernelDk<D> for Scaled<K, f64> {
+    fn eval_dk(&self, x: &[f64; D], y: &[f64; D]) -> K::Scalar { self.kernel.eval_dk(x, y) * K::Scalar::from_real(self.factor) }
+}
+
+impl<const D: usize, K: KernelDk<D, Scalar = Complex64>> KernelDk<D> for Scaled<K, Complex64> {
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> Complex64 {
+        self.kernel.eval_nodes_dk(targets, i, sources, j) * self.factor
+    }
+}
+
+impl<const D: usize, K: PointKernelDk<D, Scalar = Complex64>> PointKernelDk<D> for Scaled<K, Complex64> {
+    fn eval_dk(&self, x: &[f64; D], y: &[f64; D]) -> Complex64 { self.kernel.eval_dk(x, y) * self.factor }
+}
+
+impl<const D: usize, K1: KernelDk<D> + ScalarKernel<D>, K2: KernelDk<D, Scalar = K1::Scalar> + ScalarKernel<D>> KernelDk<D> for Sum<K1, K2> {
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K1::Scalar {
+        self.first.eval_nodes_dk(targets, i, sources, j) + self.second.eval_nodes_dk(targets, i, sources, j)
+    }
+}
+
+impl<const D: usize, K1: PointKernelDk<D> + ScalarKernel<D>, K2: PointKernelDk<D, Scalar = K1::Scalar> + ScalarKernel<D>> PointKernelDk<D> for Sum<K1, K2> {
+    fn eval_dk(&self, x: &[f64; D], y: &[f64; D]) -> K1::Scalar { self.first.eval_dk(x, y) + self.second.eval_dk(x, y) }
+}
+
+impl<const D: usize, K: PointKernelDk<D> + ScalarKernel<D>> KernelDk<D> for HalfSpace<K, D> {
+    fn eval_nodes_dk(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.eval_dk(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl<const D: usize, K: PointKernelDk<D> + ScalarKernel<D>> PointKernelDk<D> for HalfSpace<K, D> {
+    fn eval_dk(&self, x: &[f64; D], y: &[f64; D]) -> K::Scalar {
+        self.kernel.eval_dk(x, y) + K::Scalar::from_real(self.image_sign()) * self.kernel.eval_dk(x, &self.mirror(y))
+    }
+}
+
+// d/dk i/4 H0(kr) = -(ir/4) H1(kr), bounded with limit -1/(2 pi k) at r = 0 -- which is also d/dk of the
+// Nystrom self term since only helmholtz_self_constant_2d depends on k
+impl KernelDk<2> for Helmholtz {
+    fn eval_nodes_dk(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        self.eval_dk(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointKernelDk<2> for Helmholtz {
+    fn eval_dk(&self, x: &[f64; 2], y: &[f64; 2]) -> Complex64 {
+        let r: f64 = distance(x, y);
+        if r == 0.0 { return -1.0 / (2.0 * PI * self.wavenumber) }
+        -Complex64::i() * r / 4.0 * hankel1_01_complex(self.wavenumber * r).1
+    }
+}
+
+// d/dk e^{ikr} / (4 pi r) = i e^{ikr} / (4 pi), smooth everywhere
+impl KernelDk<3> for Helmholtz {
+    fn eval_nodes_dk(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        self.eval_dk(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointKernelDk<3> for Helmholtz {
+    fn eval_dk(&self, x: &[f64; 3], y: &[f64; 3]) -> Complex64 {
+        let r: f64 = distance(x, y);
+        Complex64::i() * (Complex64::i() * self.wavenumber * r).exp() / (4.0 * PI)
+    }
+}
+
+// d/dk of G' for the double layers, the H1' and (ikr - 1) terms cancel down to
+// 2D: -(ikr/4) H0(kr), 3D: -k e^{ikr} / (4 pi)
+fn helmholtz_radial_dk_2d(k: Complex64, r: f64) -> (Complex64, Complex64) {
+    (-Complex64::i() * k * r / 4.0 * hankel1_01_complex(k * r).0, Complex64::new(0.0, 0.0))
+}
+
+fn helmholtz_radial_dk_3d(k: Complex64, r: f64) -> (Complex64, Complex64) {
+    (-k * (Complex64::i() * k * r).exp() / (4.0 * PI), Complex64::new(0.0, 0.0))
+}
+
+impl KernelDk<2> for HelmholtzNormal {
+    fn eval_nodes_dk(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        layer_eval(Layer::Double, "HelmholtzNormal", targets, i, sources, j, |r| helmholtz_radial_dk_2d(self.wavenumber, r))
+    }
+}
+
+impl KernelDk<3> for HelmholtzNormal {
+    fn eval_nodes_dk(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        layer_eval(Layer::Double, "HelmholtzNormal", targets, i, sources, j, |r| helmholtz_radial_dk_3d(self.wavenumber, r))
+    }
+}
+
+impl KernelDk<2> for HelmholtzAdjointNormal {
+    fn eval_nodes_dk(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        layer_eval(Layer::AdjointDouble, "HelmholtzAdjointNormal", targets, i, sources, j, |r| helmholtz_radial_dk_2d(self.wavenumber, r))
+    }
+}
+
+impl KernelDk<3> for HelmholtzAdjointNormal {
+    fn eval_nodes_dk(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        layer_eval(Layer::AdjointDouble, "HelmholtzAdjointNormal", targets, i, sources, j, |r| helmholtz_radial_dk_3d(self.wavenumber, r))
+    }
+}
+
+// eta is held fixed, so this is dD/dk - i eta dS/dk even when eta was picked as |k| by new
+impl KernelDk<2> for CombinedField {
+    fn eval_nodes_dk(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> Complex64 {
+        let double: Complex64 = HelmholtzNormal::new(self.wavenumber).eval_nodes_dk(targets, i, sources, j);
+        let single: Complex64 = Helmholtz::new(self.wavenumber).eval_nodes_dk(targets, i, sources, j);
+        double - Complex64::i() * self.eta * single
+    }
+}
+
+impl KernelDk<3> for CombinedField {
+    fn eval_nodes_dk(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> Complex64 {
+        let double: Complex64 = HelmholtzNormal::new(self.wavenumber).eval_nodes_dk(targets, i, sources, j);
+        let single: Complex64 = Helmholtz::new(self.wavenumber).eval_nodes_dk(targets, i, sources, j);
+        double - Complex64::i() * self.eta * single
+    }
+}
+
+#[cfg(test)]
+mod kernel_tests {
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    // central difference of the single layer in y along n_y
+    fn fd_normal<const D: usize, K: PointKernel<D>>(kernel: &K, x: &[f64; D], y: &[f64; D], ny: &[f64; D]) -> Complex64 {
+        let h: f64 = 1e-5;
+        let mut yp: [f64; D] = *y;
+        let mut ym: [f64; D] = *y;
+        for d in 0..D { yp[d] += h * ny[d]; ym[d] -= h * ny[d]; }
+        (kernel.eval(x, &yp) - kernel.eval(x, &ym)).to_complex() / (2.0 * h)
+    }
+
+    #[test]
+    fn helmholtz_normal_2d_matches_fd() {
+        let (x, y, ny) = ([0.3, -0.2], [1.1, 0.4], [0.6, 0.8]);
+        let targets: Nodes<2> = Nodes::new(vec![x]);
+        let sources: Nodes<2> = Nodes::with_normals(vec![y], vec![ny]);
+        let exact: Complex64 = HelmholtzNormal::new(3.0).eval_nodes(&targets, 0, &sources, 0);
+        let fd: Complex64 = fd_normal(&Helmholtz::new(3.0), &x, &y, &ny);
+        assert!((exact - fd).norm() < 1e-4 * exact.norm());
+    }
+
+    #[test]
+    fn helmholtz_normal_3d_matches_fd() {
+        let (x, y, ny) = ([0.3, -0.2, 0.5], [1.1, 0.4, -0.3], [0.0, 0.6, 0.8]);
+        let targets: Nodes<3> = Nodes::new(vec![x]);
+        let sources: Nodes<3> = Nodes::with_normals(vec![y], vec![ny]);
+        let exact: Complex64 = HelmholtzNormal::new(2.0).eval_nodes(&targets, 0, &sources, 0);
+        let fd: Complex64 = fd_normal(&Helmholtz::new(2.0), &x, &y, &ny);
+        assert!((exact - fd).norm() < 1e-7 * exact.norm());
+    }
+
+    // central difference of the single layer in x along n_x
+    fn fd_adjoint<const D: usize, K: PointKernel<D>>(kernel: &K, x: &[f64; D], nx: &[f64; D], y: &[f64; D]) -> Complex64 {
+        let h: f64 = 1e-5;
+        let mut xp: [f64; D] = *x;
+        let mut xm: [f64; D] = *x;
+        for d in 0..D { xp[d] += h * nx[d]; xm[d] -= h * nx[d]; }
+        (kernel.eval(&xp, y) - kernel.eval(&xm, y)).to_complex() / (2.0 * h)
+    }
+
+    // mixed second difference of the single layer along a at x and b at y
+    fn fd_mixed<const D: usize, K: PointKernel<D>>(kernel: &K, x: &[f64; D], a: &[f64; D], y: &[f64; D], b: &[f64; D]) -> Complex64 {
+        let h: f64 = 1e-4;
+        let shift = |p: &[f64; D], dir: &[f64; D], s: f64| -> [f64; D] {
+            let mut q: [f64; D] = *p;
+            for d in 0..D { q[d] += s * h * dir[d]; }
+            q
+        };
+        let (xp, xm, yp, ym) = (shift(x, a, 1.0), shift(x, a, -1.0), shift(y, b, 1.0), shift(y, b, -1.0));
+        (kernel.eval(&xp, &yp) - kernel.eval(&xp, &ym) - kernel.eval(&xm, &yp) + kernel.eval(&xm, &ym)).to_complex() / (4.0 * h * h)
+    }
+
+    fn close(a: Complex64, b: Complex64, tol: f64) -> bool {
+        (a - b).norm() < tol * b.norm()
+    }
+
+    #[test]
+    fn laplace_layers_match_fd() {
+        let (x, nx, y, ny) = ([0.3, -0.2], [0.0, 1.0], [1.1, 0.4], [0.6, 0.8]);
+        let targets: Nodes<2> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<2> = Nodes::with_normals(vec![y], vec![ny]);
+        assert!(close(LaplaceNormal.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_normal(&Laplace, &x, &y, &ny), 1e-8));
+        assert!(close(LaplaceAdjointNormal.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_adjoint(&Laplace, &x, &nx, &y), 1e-8));
+        assert!(close(LaplaceHypersingular.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_mixed(&Laplace, &x, &nx, &y, &ny), 1e-6));
+
+        let (x, nx, y, ny) = ([0.3, -0.2, 0.5], [1.0, 0.0, 0.0], [1.1, 0.4, -0.3], [0.0, 0.6, 0.8]);
+        let targets: Nodes<3> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<3> = Nodes::with_normals(vec![y], vec![ny]);
+        assert!(close(LaplaceNormal.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_normal(&Laplace, &x, &y, &ny), 1e-8));
+        assert!(close(LaplaceAdjointNormal.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_adjoint(&Laplace, &x, &nx, &y), 1e-8));
+        assert!(close(LaplaceHypersingular.eval_nodes(&targets, 0, &sources, 0).to_complex(), fd_mixed(&Laplace, &x, &nx, &y, &ny), 1e-6));
+    }
+
+    #[test]
+    fn helmholtz_layers_match_fd() {
+        let (x, nx, y, ny) = ([0.3, -0.2], [0.0, 1.0], [1.1, 0.4], [0.6, 0.8]);
+        let targets: Nodes<2> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<2> = Nodes::with_normals(vec![y], vec![ny]);
+        let single: Helmholtz = Helmholtz::new(3.0);
+        assert!(close(HelmholtzAdjointNormal::new(3.0).eval_nodes(&targets, 0, &sources, 0), fd_adjoint(&single, &x, &nx, &y), 1e-4));
+        assert!(close(HelmholtzHypersingular::new(3.0).eval_nodes(&targets, 0, &sources, 0), fd_mixed(&single, &x, &nx, &y, &ny), 1e-4));
+
+        let (x, nx, y, ny) = ([0.3, -0.2, 0.5], [1.0, 0.0, 0.0], [1.1, 0.4, -0.3], [0.0, 0.6, 0.8]);
+        let targets: Nodes<3> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<3> = Nodes::with_normals(vec![y], vec![ny]);
+        let single: Helmholtz = Helmholtz::new(2.0);
+        assert!(close(HelmholtzAdjointNormal::new(2.0).eval_nodes(&targets, 0, &sources, 0), fd_adjoint(&single, &x, &nx, &y), 1e-7));
+        assert!(close(HelmholtzHypersingular::new(2.0).eval_nodes(&targets, 0, &sources, 0), fd_mixed(&single, &x, &nx, &y, &ny), 1e-6));
+    }
+
+    // pointwise Maue identity d2G/dn_x dn_y = k^2 n_x.n_y G - d2G/ds_x ds_y, tangents t = (-n_1, n_0)
+    #[test]
+    fn maue_identity_2d() {
+        let (x, nx, y, ny) = ([0.3, -0.2], [0.0, 1.0], [1.1, 0.4], [0.6, 0.8]);
+        let (tx, ty) = ([-nx[1], nx[0]], [-ny[1], ny[0]]);
+        let targets: Nodes<2> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<2> = Nodes::with_normals(vec![y], vec![ny]);
+
+        let single: Laplace = LaplaceHypersingular.maue();
+        let expected: Complex64 = -fd_mixed(&single, &x, &tx, &y, &ty);
+        assert!(close(LaplaceHypersingular.eval_nodes(&targets, 0, &sources, 0).to_complex(), expected, 1e-6));
+
+        let kernel: HelmholtzHypersingular = HelmholtzHypersingular::new(3.0);
+        let (single, normal_single) = kernel.maue();
+        let expected: Complex64 = normal_single.eval_nodes(&targets, 0, &sources, 0) - fd_mixed(&single, &x, &tx, &y, &ty);
+        assert!(close(kernel.eval_nodes(&targets, 0, &sources, 0), expected, 1e-4));
+    }
+
+    #[test]
+    fn combined_field_is_d_minus_i_eta_s() {
+        let (x, y, ny) = ([0.3, -0.2, 0.5], [1.1, 0.4, -0.3], [0.0, 0.6, 0.8]);
+        let targets: Nodes<3> = Nodes::new(vec![x]);
+        let sources: Nodes<3> = Nodes::with_normals(vec![y], vec![ny]);
+        let double: Complex64 = HelmholtzNormal::new(2.0).eval_nodes(&targets, 0, &sources, 0);
+        let single: Complex64 = Helmholtz::new(2.0).eval(&x, &y);
+
+        let default: Complex64 = CombinedField::new(2.0).eval_nodes(&targets, 0, &sources, 0);
+        assert!((default - (double - Complex64::i() * 2.0 * single)).norm() < 1e-14);
+
+        let coupled: Complex64 = CombinedField::with_coupling(2.0, 0.5).eval_nodes(&targets, 0, &sources, 0);
+        assert!((coupled - (double - Complex64::i() * 0.5 * single)).norm() < 1e-14);
+    }
+
+    #[test]
+    fn modified_helmholtz_values() {
+        // K0(1.5) / 2pi at r = 0.5, lambda = 3
+        let value: f64 = ModifiedHelmholtz::new(3.0).eval_2d(&[0.0, 0.0], &[0.3, 0.4]);
+        assert!((value - 0.21380556264752574 / (2.0 * PI)).abs() < 1e-15);
+
+        let value: f64 = ModifiedHelmholtz::new(3.0).eval_3d(&[0.0, 0.0, 0.0], &[0.3, 0.4, 0.0]);
+        assert!((value - (-1.5_f64).exp() / (2.0 * PI)).abs() < 1e-15);
+
+        // lambda -> 0 in 3D is Laplace
+        let x: [f64; 3] = [0.1, 0.2, 0.3];
+        let y: [f64; 3] = [0.9, -0.4, 0.0];
+        assert!((ModifiedHelmholtz::new(1e-10).eval(&x, &y) - Laplace.eval(&x, &y)).abs() < 1e-9);
+
+        // coincident nodes get the smooth remainder G - 1/(4 pi r) at r = 0, not a clamped 1/r
+        let nodes: Nodes<3> = Nodes::new(vec![x, y]);
+        let kernel: ModifiedHelmholtz = ModifiedHelmholtz::new(3.0);
+        assert_eq!(kernel.eval_nodes(&nodes, 1, &nodes, 1), -3.0 / (4.0 * PI));
+        let remainder: f64 = kernel.eval_3d(&[0.0, 0.0, 0.0], &[0.0, 0.0, 1e-8]) - 1e8 / (4.0 * PI);
+        assert!((remainder + 3.0 / (4.0 * PI)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matern_closed_forms_match_bessel() {
+        for nu in [0.5, 1.5, 2.5] {
+            let kernel: Matern = Matern::new(nu, 0.7, 2.0);
+            for r in [0.0, 0.01, 0.3, 1.0, 4.0] {
+                assert!((kernel.eval_real(r) - kernel.eval_general(r)).abs() < 1e-12, "nu = {}, r = {}", nu, r);
+            }
+        }
+        // general nu sits between its neighbours
+        let r: f64 = 0.8;
+        let (lo, mid, hi) = (Matern::new(1.5, 1.0, 1.0), Matern::new(2.0, 1.0, 1.0), Matern::new(2.5, 1.0, 1.0));
+        assert!(lo.eval_real(r) < mid.eval_real(r) && mid.eval_real(r) < hi.eval_real(r));
+    }
+
+    #[test]
+    fn covariances_work_in_any_dimension() {
+        let x: [f64; 5] = [0.0, 0.1, 0.2, 0.3, 0.4];
+        let y: [f64; 5] = [0.5, 0.1, -0.2, 0.3, 0.0];
+        let r: f64 = (0.25_f64 + 0.16 + 0.16).sqrt();
+
+        assert!((Gaussian::new(0.5, 3.0).eval(&x, &y) - 3.0 * (-0.5 * r * r / 0.25).exp()).abs() < 1e-15);
+        assert!((Matern::exponential(0.5, 3.0).eval(&x, &x) - 3.0).abs() < 1e-15);
+        assert!((Matern::new(0.8, 0.5, 3.0).eval(&x, &x) - 3.0).abs() < 1e-15);
+        let rq: f64 = RationalQuadratic::new(2.0, 0.5, 3.0).eval(&x, &y);
+        assert!((rq - 3.0 * (1.0 + r * r / (4.0 * 0.25)).powf(-2.0)).abs() < 1e-15);
+
+        // large alpha recovers the Gaussian
+        let rq: f64 = RationalQuadratic::new(1e8, 0.5, 3.0).eval_real(r);
+        assert!((rq - Gaussian::new(0.5, 3.0).eval_real(r)).abs() < 1e-7);
+    }
+
+    #[test]
+    fn helmholtz_3d_small_k_is_laplace() {
+        let (x, y) = ([0.0, 0.0, 0.0], [0.5, 0.2, 0.1]);
+        let helmholtz: Complex64 = Helmholtz::new(1e-8).eval(&x, &y);
+        let laplace: Complex64 = Laplace.eval(&x, &y).to_complex();
+        assert!((helmholtz - laplace).norm() < 1e-7);
+    }
+
+    fn unit_circle(n: usize) -> Nodes<2> {
+        let points: Vec<[f64; 2]> = (0..n).map(|i| {
+            let t: f64 = 2.0 * PI * i as f64 / n as f64;
+            [t.cos(), t.sin()]
+        }).collect();
+        Nodes::with_boundary(points.clone(), vec![2.0 * PI / n as f64; n], points).set_curvatures(vec![1.0; n])
+    }
+
+    #[test]
+    fn repeated_point_is_not_the_diagonal() {
+        // nodes 0 and 1 sit on the same point, only (0, 0) and (1, 1) are diagonal entries
+        let points: Vec<[f64; 2]> = vec![[0.5, 0.0], [0.5, 0.0], [0.0, 1.0]];
+        let nodes: Nodes<2> = Nodes::new(points.clone());
+        let kernel = SelfTerm::new(Gaussian::new(0.5, 2.0), |_: &Nodes<2>, _: usize| -1.0);
+        assert_eq!(kernel.eval_nodes(&nodes, 0, &nodes, 0), -1.0);
+        assert_eq!(kernel.eval_nodes(&nodes, 0, &nodes, 1), 2.0);
+        assert_eq!(kernel.eval_nodes(&nodes, 1, &nodes, 1), -1.0);
+
+        // and neither is the same index in a different Nodes holding the same points
+        let copy: Nodes<2> = Nodes::new(points);
+        assert_eq!(kernel.eval_nodes(&copy, 0, &nodes, 0), 2.0);
+
+        let weighted: Nodes<2> = Nodes::with_weights(vec![[0.5, 0.0], [0.5, 0.0]], vec![0.1, 0.1]);
+        assert_eq!(Laplace.eval_nodes(&weighted, 0, &weighted, 0), laplace_self_2d(&weighted, 0).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Laplace is singular at r = 0")]
+    fn repeated_point_without_self_term_panics() {
+        let nodes: Nodes<2> = Nodes::with_weights(vec![[0.5, 0.0], [0.5, 0.0]], vec![0.1, 0.1]);
+        Laplace.eval_nodes(&nodes, 0, &nodes, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Helmholtz is singular at r = 0")]
+    fn diagonal_without_weights_panics() {
+        let nodes: Nodes<2> = Nodes::new(vec![[0.5, 0.0], [0.0, 1.0]]);
+        Helmholtz::new(2.0).eval_nodes(&nodes, 1, &nodes, 1);
+    }
+
+    #[test]
+    fn double_layer_diagonal_is_curvature_limit() {
+        // (x - y).n_y / r^2 = -1/2 for any two points on the unit circle, so the whole matrix is -1/4pi
+        let n: usize = 16;
+        let nodes: Nodes<2> = unit_circle(n);
+        for i in 0..n {
+            for j in [0, 1, i] {
+                assert!((LaplaceNormal.eval_nodes(&nodes, i, &nodes, j) + 0.25 / PI).abs() < 1e-14);
+                assert!((LaplaceAdjointNormal.eval_nodes(&nodes, i, &nodes, j) + 0.25 / PI).abs() < 1e-14);
+            }
+        }
+
+        // Helmholtz shares the limit, approached as a neighbour closes in
+        let kernel: HelmholtzNormal = HelmholtzNormal::new(3.0);
+        let diagonal: Complex64 = kernel.eval_nodes(&nodes, 0, &nodes, 0);
+        assert_eq!(diagonal, Complex64::from(-0.25 / PI));
+        let near: Nodes<2> = Nodes::with_normals(vec![[1e-4_f64.cos(), 1e-4_f64.sin()]], vec![[1e-4_f64.cos(), 1e-4_f64.sin()]]);
+        assert!((kernel.eval_nodes(&nodes, 0, &near, 0) - diagonal).norm() < 1e-6);
+
+        // combined field diagonal is D - i eta S from the two self terms
+        let combined: CombinedField = CombinedField::new(3.0);
+        let single: Complex64 = Helmholtz::new(3.0).eval_self(&nodes, 0).unwrap();
+        assert!((combined.eval_nodes(&nodes, 0, &nodes, 0) - (diagonal - Complex64::i() * 3.0 * single)).norm() < 1e-14);
+
+        // no curvature, no self term
+        let bare: Nodes<2> = Nodes::with_boundary(nodes.points.clone(), nodes.weights.clone().unwrap(), nodes.points.clone());
+        assert_eq!(LaplaceNormal.eval_nodes(&bare, 2, &bare, 2), 0.0);
+    }
+
+    #[test]
+    fn self_terms_give_accurate_single_layer_on_circle() {
+        // S[cos t] = cos t / 2 on the unit circle for Laplace, S[1] = I0(lambda) K0(lambda) for Yukawa
+        let n: usize = 64;
+        let nodes: Nodes<2> = unit_circle(n);
+        for i in [0, 5, 40] {
+            let t_i: f64 = 2.0 * PI * i as f64 / n as f64;
+            let laplace: f64 = (0..n)
+                .map(|j| Laplace.eval_nodes(&nodes, i, &nodes, j) * nodes.weight(j) * (2.0 * PI * j as f64 / n as f64).cos())
+                .sum();
+            assert!((laplace - 0.5 * t_i.cos()).abs() < 1e-5);
+
+            let yukawa: f64 = (0..n).map(|j| ModifiedHelmholtz::new(2.0).eval_nodes(&nodes, i, &nodes, j) * nodes.weight(j)).sum();
+            assert!((yukawa - 0.25963079834597075).abs() < 1e-5);
+        }
+
+        // caller supplied diagonal wins over the built in one
+        let zeroed = SelfTerm::new(Laplace, |_: &Nodes<2>, _| 0.0);
+        assert_eq!(zeroed.eval_nodes(&nodes, 3, &nodes, 3), 0.0);
+        assert_eq!(zeroed.eval_nodes(&nodes, 3, &nodes, 4), Laplace.eval_nodes(&nodes, 3, &nodes, 4));
+    }
+
+    #[test]
+    fn helmholtz_self_constant_is_smooth_limit() {
+        // i/4 - (log(k/2) + gamma) / 2pi at k = 3 from mpmath, then check it really is what i/4 H0(kr) + log(r) / 2pi tends to
+        // (and that the kernel matches mpmath at r = 1e-4 itself)
+        let constant: Complex64 = helmholtz_self_constant_2d(Complex64::new(3.0, 0.0));
+        assert!((constant - Complex64::new(-0.15639850250585808, 0.25)).norm() < 1e-15);
+
+        let (x, y): ([f64; 2], [f64; 2]) = ([0.0, 0.0], [1e-4, 0.0]);
+        let smooth: Complex64 = Helmholtz::new(3.0).eval(&x, &y) - Laplace.eval(&x, &y);
+        assert!((smooth - constant).norm() < 1e-7);
+        assert!((smooth - Complex64::new(-0.15639853554997976, 0.24999999437500003)).norm() < 1e-13);
+    }
+
+    #[test]
+    fn complex_wavenumber() {
+        // k = 2 + 0.5i at r = 0.5, i/4 H0(kr) from mpmath
+        let k: Complex64 = Complex64::new(2.0, 0.5);
+        let (x, y): ([f64; 2], [f64; 2]) = ([0.1, 0.2], [0.4, 0.6]);
+        let value: Complex64 = Helmholtz::new(k).eval(&x, &y);
+        assert!((value - Complex64::new(-0.0010133105842216534, 0.14556015692605548)).norm() < 1e-14);
+
+        // real part only goes back to the real Hankel path
+        assert_eq!(Helmholtz::new(Complex64::new(3.0, 0.0)).eval(&x, &y), Helmholtz::new(3.0).eval(&x, &y));
+
+        let (x3, y3): ([f64; 3], [f64; 3]) = ([0.3, -0.2, 0.5], [1.1, 0.4, -0.3]);
+        let r: f64 = distance(&x3, &y3);
+        let expected: Complex64 = (Complex64::i() * k * r).exp() / (4.0 * PI * r);
+        assert!((Helmholtz::new(k).eval(&x3, &y3) - expected).norm() < 1e-15);
+
+        // layers still differentiate the complex kernel correctly
+        let (x, nx, y, ny) = ([0.3, -0.2], [0.0, 1.0], [1.1, 0.4], [0.6, 0.8]);
+        let targets: Nodes<2> = Nodes::with_normals(vec![x], vec![nx]);
+        let sources: Nodes<2> = Nodes::with_normals(vec![y], vec![ny]);
+        let single: Helmholtz = Helmholtz::new(k);
+        assert!(close(HelmholtzNormal::new(k).eval_nodes(&targets, 0, &sources, 0), fd_normal(&single, &x, &y, &ny), 1e-7));
+        assert!(close(HelmholtzHypersingular::new(k).eval_nodes(&targets, 0, &sources, 0), fd_mixed(&single, &x, &nx, &y, &ny), 1e-5));
+
+        // and the diagonal constant is still the smooth limit of G - G_laplace
+        let (x, y): ([f64; 2], [f64; 2]) = ([0.0, 0.0], [1e-6, 0.0]);
+        let smooth: Complex64 = single.eval(&x, &y) - Laplace.eval(&x, &y);
+        assert!((smooth - helmholtz_self_constant_2d(k)).norm() < 1e-9);
+        assert_eq!(Kernel::<2>::decay_rate(&single), 0.5);
+    }
+
+    #[test]
+    fn wavenumber_derivatives_match_fd() {
+        let h: f64 = 1e-5;
+        let (x2, nx2, y2, ny2) = ([0.3, -0.2], [0.0, 1.0], [1.1, 0.4], [0.6, 0.8]);
+        let (x3, nx3, y3, ny3) = ([0.3, -0.2, 0.5], [1.0, 0.0, 0.0], [1.1, 0.4, -0.3], [0.0, 0.6, 0.8]);
+        let (t2, s2): (Nodes<2>, Nodes<2>) = (Nodes::with_normals(vec![x2], vec![nx2]), Nodes::with_normals(vec![y2], vec![ny2]));
+        let (t3, s3): (Nodes<3>, Nodes<3>) = (Nodes::with_normals(vec![x3], vec![nx3]), Nodes::with_normals(vec![y3], vec![ny3]));
+        let circle: Nodes<2> = unit_circle(32);
+
+        for k in [Complex64::new(3.0, 0.0), Complex64::new(2.0, 0.5)] {
+            let (kp, km): (Complex64, Complex64) = (k + h, k - h);
+            let fd = |plus: Complex64, minus: Complex64| (plus - minus) / (2.0 * h);
+
+            let g: Helmholtz = Helmholtz::new(k);
+            assert!(close(g.eval_dk(&x2, &y2), fd(Helmholtz::new(kp).eval(&x2, &y2), Helmholtz::new(km).eval(&x2, &y2)), 1e-8));
+            assert!(close(g.eval_dk(&x3, &y3), fd(Helmholtz::new(kp).eval(&x3, &y3), Helmholtz::new(km).eval(&x3, &y3)), 1e-8));
+
+            // coincident 2D: limit of the kernel matches d/dk of the Nystrom self term
+            let diag: Complex64 = fd(Helmholtz::new(kp).eval_nodes(&circle, 5, &circle, 5), Helmholtz::new(km).eval_nodes(&circle, 5, &circle, 5));
+            assert!(close(g.eval_nodes_dk(&circle, 5, &circle, 5), diag, 1e-8));
+
+            let double2 = |k: Complex64| HelmholtzNormal::new(k).eval_nodes(&t2, 0, &s2, 0);
+            let adjoint3 = |k: Complex64| HelmholtzAdjointNormal::new(k).eval_nodes(&t3, 0, &s3, 0);
+            let combined2 = |k: Complex64| CombinedField::with_coupling(k, 3.0).eval_nodes(&t2, 0, &s2, 0);
+            assert!(close(HelmholtzNormal::new(k).eval_nodes_dk(&t2, 0, &s2, 0), fd(double2(kp), double2(km)), 1e-8));
+            assert!(close(HelmholtzAdjointNormal::new(k).eval_nodes_dk(&t3, 0, &s3, 0), fd(adjoint3(kp), adjoint3(km)), 1e-8));
+            assert!(close(CombinedField::with_coupling(k, 3.0).eval_nodes_dk(&t2, 0, &s2, 0), fd(combined2(kp), combined2(km)), 1e-8));
+        }
+    }
+
+    #[test]
+    fn combinators_match_their_parts() {
+        let circle: Nodes<2> = unit_circle(40);
+        let (x, y): ([f64; 2], [f64; 2]) = ([0.3, -0.2], [1.1, 0.4]);
+        let sum = Sum::new(Laplace, Scaled::new(Gaussian::new(0.5, 2.0), 3.0));
+        assert!((sum.eval(&x, &y) - Laplace.eval(&x, &y) - 3.0 * Gaussian::new(0.5, 2.0).eval(&x, &y)).abs() < 1e-15);
+        // Laplace keeps its self term inside the sum
+        let diag: f64 = Laplace.eval_nodes(&circle, 4, &circle, 4) + 3.0 * Gaussian::new(0.5, 2.0).eval(&x, &x);
+        assert!((sum.eval_nodes(&circle, 4, &circle, 4) - diag).abs() < 1e-15);
+        assert_eq!(sum.eval_self(&circle, 4), Some(sum.eval_nodes(&circle, 4, &circle, 4)));
+        block_matches_entries(&sum, &circle, &(0..12).collect::<Vec<usize>>(), &(5..30).collect::<Vec<usize>>());
+
+        // Brakhage-Werner put together by hand is CombinedField, derivative included
+        let k: Complex64 = Complex64::new(3.0, 0.2);
+        let by_hand = Sum::new(HelmholtzNormal::new(k), Scaled::new(Helmholtz::new(k), Complex64::new(0.0, -2.0)));
+        let combined: CombinedField = CombinedField::with_coupling(k, 2.0);
+        for (i, j) in [(0, 7), (3, 3), (12, 30)] {
+            assert!((Kernel::<2>::eval_nodes(&by_hand, &circle, i, &circle, j) - combined.eval_nodes(&circle, i, &circle, j)).norm() < 1e-15);
+            assert!((KernelDk::<2>::eval_nodes_dk(&by_hand, &circle, i, &circle, j) - combined.eval_nodes_dk(&circle, i, &circle, j)).norm() < 1e-15);
+        }
+        assert_eq!(Kernel::<2>::decay_rate(&by_hand), 0.2);
+
+        // closures, real and complex
+        let laplace = FnKernel::new(|x: &[f64; 2], y: &[f64; 2]| -distance(x, y).ln() / (2.0 * PI));
+        assert_eq!(laplace.eval(&x, &y), Laplace.eval(&x, &y));
+        let plane_wave = FnKernel::new(|x: &[f64; 3], y: &[f64; 3]| Complex64::from_polar(1.0, 2.0 * (x[0] - y[0])));
+        assert_eq!(plane_wave.eval(&[0.5, 0.0, 0.0], &[0.0, 1.0, 2.0]), Complex64::from_polar(1.0, 1.0));
+    }
+
+    #[test]
+    fn quasi_periodic_matches_image_sums() {
+        let pairs: [([f64; 2], [f64; 2]); 3] = [([0.3, 0.2], [0.0, 0.0]), ([0.1, -0.05], [0.5, 0.4]), ([3.7, 1.1], [0.2, -1.4])];
+        let image = |y: &[f64; 2], n: i64, d: f64| [y[0] + n as f64 * d, y[1]];
+
+        // absorbing k: the image sum converges geometrically, 300 each side is e^{-195}
+        let (k, d, alpha): (Complex64, f64, f64) = (Complex64::new(3.0, 0.5), 1.3, 0.4);
+        let grating: QuasiPeriodicHelmholtz = QuasiPeriodicHelmholtz::new(k, d, alpha);
+        for (x, y) in &pairs {
+            let direct: Complex64 = (-300..=300).map(|n: i64| {
+                Complex64::from_polar(1.0, alpha * n as f64 * d) * Helmholtz::new(k).eval(x, &image(y, n, d))
+            }).sum();
+            assert!((grating.eval(x, y) - direct).norm() < 1e-12 * direct.norm(), "{:?} {:?}", x, y);
+        }
+
+        // real k: only conditionally convergent, so smoothly window the images out between N/2 and N
+        let bump = |t: f64| -> f64 {
+            let s: f64 = 2.0 * t.abs() - 1.0;
+            if s <= 0.0 { return 1.0 }
+            if s >= 1.0 { return 0.0 }
+            let (a, b): (f64, f64) = ((-1.0 / s).exp(), (-1.0 / (1.0 - s)).exp());
+            b / (a + b)
+        };
+        let (k, d, alpha, n_max): (f64, f64, f64, i64) = (4.0, 1.0, 1.0, 400);
+        let grating: QuasiPeriodicHelmholtz = QuasiPeriodicHelmholtz::new(k, d, alpha);
+        for (x, y) in &pairs {
+            let direct: Complex64 = (-n_max..=n_max).map(|n: i64| {
+                Complex64::from_polar(bump(n as f64 / n_max as f64), alpha * n as f64 * d) * Helmholtz::new(k).eval(x, &image(y, n, d))
+            }).sum();
+            assert!((grating.eval(x, y) - direct).norm() < 1e-9 * direct.norm(), "{:?} {:?}", x, y);
+        }
+
+        // Bloch condition, and the self constant is the smooth limit of G + log(r) / 2pi (averaged over +-r, the
+        // regular part has a gradient)
+        let (x, y): ([f64; 2], [f64; 2]) = ([0.3, 0.2], [0.0, 0.0]);
+        let shifted: Complex64 = grating.eval(&[x[0] + d, x[1]], &y);
+        assert!((shifted - Complex64::from_polar(1.0, alpha * d) * grating.eval(&x, &y)).norm() < 1e-13);
+        let r: f64 = 1e-6;
+        let smooth: Complex64 = (grating.eval(&[r, 0.0], &[0.0, 0.0]) + grating.eval(&[-r, 0.0], &[0.0, 0.0])) / 2.0 + r.ln() / (2.0 * PI);
+        assert!((smooth - grating.self_constant()).norm() < 1e-9);
+    }
+
+    #[test]
+    fn half_space_boundary_conditions() {
+        // tilted plane x + y = 0.5 in 2D, ground z = 0 in 3D
+        let h: f64 = 1e-5;
+        let n2: [f64; 2] = [FRAC_1_SQRT_2, FRAC_1_SQRT_2];
+        let (on2, y2): ([f64; 2], [f64; 2]) = ([0.2, 0.3], [1.0, 0.7]);
+        let soft = HalfSpace::with_plane(Laplace, Boundary::Dirichlet, [1.0, 1.0], 0.5);
+        let rigid = HalfSpace::with_plane(Laplace, Boundary::Neumann, [1.0, 1.0], 0.5);
+        assert!(soft.eval(&on2, &y2).abs() < 1e-15);
+        let normal_derivative: f64 = (rigid.eval(&[on2[0] + h * n2[0], on2[1] + h * n2[1]], &y2)
+            - rigid.eval(&[on2[0] - h * n2[0], on2[1] - h * n2[1]], &y2)) / (2.0 * h);
+        assert!(normal_derivative.abs() < 1e-9);
+
+        let (on3, y3): ([f64; 3], [f64; 3]) = ([0.3, -0.2, 0.0], [1.1, 0.4, 0.8]);
+        let soft = HalfSpace::new(Helmholtz::new(2.0), Boundary::Dirichlet);
+        let rigid = HalfSpace::new(Helmholtz::new(2.0), Boundary::Neumann);
+        assert!(soft.eval(&on3, &y3).norm() < 1e-15);
+        let dz: Complex64 = (rigid.eval(&[on3[0], on3[1], h], &y3) - rigid.eval(&[on3[0], on3[1], -h], &y3)) / (2.0 * h);
+        assert!(dz.norm() < 1e-9);
+        // still reciprocal, and the image really is the mirrored free space kernel
+        let x3: [f64; 3] = [-0.5, 0.1, 0.3];
+        assert!((rigid.eval(&x3, &y3) - rigid.eval(&y3, &x3)).norm() < 1e-15);
+        let image: Complex64 = Helmholtz::new(2.0).eval(&x3, &[1.1, 0.4, -0.8]);
+        assert!((rigid.eval(&x3, &y3) - Helmholtz::new(2.0).eval(&x3, &y3) - image).norm() < 1e-15);
+
+        // circle above the line y = -1.5, plus one node sitting on the plane itself (Neumann, Dirichlet rows there are all ~0)
+        let mut points: Vec<[f64; 2]> = unit_circle(30).points;
+        points.push([0.25, -1.5]);
+        let nodes: Nodes<2> = Nodes::with_weights(points, vec![0.2; 31]);
+        let (rows, cols): (Vec<usize>, Vec<usize>) = ((0..12).chain([30]).collect(), (5..31).collect());
+        block_matches_entries(&HalfSpace::with_plane(Laplace, Boundary::Neumann, [0.0, 2.0], -3.0), &nodes, &rows, &cols);
+        block_matches_entries(&Weighted::new(HalfSpace::with_plane(Helmholtz::new(2.0), Boundary::Neumann, [0.0, 1.0], -1.5)), &nodes, &rows, &cols);
+    }
+
+    fn block_matches_entries<const D: usize, K: Kernel<D>>(kernel: &K, nodes: &Nodes<D>, rows: &[usize], cols: &[usize]) {
+        let mut out: Vec<K::Scalar> = vec![K::Scalar::zero(); rows.len() * cols.len()];
+        kernel.eval_block(nodes, rows, nodes, cols, &mut out);
+        for (a, &i) in rows.iter().enumerate() {
+            for (b, &j) in cols.iter().enumerate() {
+                let entry: K::Scalar = kernel.eval_nodes(nodes, i, nodes, j);
+                assert!((out[a * cols.len() + b] - entry).abs() <= 1e-13 * entry.abs(), "({}, {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn eval_block_matches_eval_nodes() {
+        // overlapping rows and cols so the coincident self terms get exercised too
+        let circle: Nodes<2> = unit_circle(40);
+        let (rows, cols): (Vec<usize>, Vec<usize>) = ((0..12).collect(), (5..30).collect());
+        block_matches_entries(&Laplace, &circle, &rows, &cols);
+        block_matches_entries(&Weighted::new(Laplace), &circle, &rows, &cols);
+        block_matches_entries(&Gaussian::new(0.5, 2.0), &circle, &rows, &cols);
+        block_matches_entries(&Helmholtz::new(2.0), &circle, &[3, 4], &[2, 3, 7]);
+
+        let points: Vec<[f64; 3]> = (0..30).map(|i| {
+            let t: f64 = i as f64;
+            [t.sin(), (0.7 * t).cos(), 0.1 * t]
+        }).collect();
+        let cloud: Nodes<3> = Nodes::new(points);
+        // no 3D self terms, so keep off the diagonal
+        let cols: Vec<usize> = (12..30).collect();
+        block_matches_entries(&Laplace, &cloud, &rows, &cols);
+        block_matches_entries(&Helmholtz::new(3.0), &cloud, &rows, &cols);
+    }
+
+    #[test]
+    fn axisymmetric_rings_match_3d_kernels() {
+        // brute force trapezoid round the source ring with the 3D kernels, pairs near / moderate / far and one on the axis
+        let pairs: [([f64; 2], [f64; 2]); 4] = [
+            ([1.0, 0.0], [1.05, 0.02]),
+            ([1.0, 0.0], [1.5, 0.4]),
+            ([0.3, 0.2], [2.0, -1.5]),
+            ([0.0, 0.5], [0.8, -0.1]),
+        ];
+        let n: usize = 20000;
+        let ring = |x: &[f64; 2], y: &[f64; 2], mode: usize, kernel: &dyn Fn(&[f64; 3], &[f64; 3]) -> Complex64| -> Complex64 {
+            let target: [f64; 3] = [x[0], 0.0, x[1]];
+            let total: Complex64 = (0..n).map(|j| {
+                let phi: f64 = 2.0 * PI * j as f64 / n as f64;
+                kernel(&target, &[y[0] * phi.cos(), y[0] * phi.sin(), y[1]]) * (mode as f64 * phi).cos()
+            }).sum();
+            total * y[0] * 2.0 * PI / n as f64
+        };
+        for (x, y) in pairs {
+            for mode in [0, 1, 2, 5, 12] {
+                let expected: Complex64 = ring(&x, &y, mode, &|a, b| Complex64::new(PointKernel::<3>::eval(&Laplace, a, b), 0.0));
+                let value: f64 = AxisymmetricLaplace::new(mode).eval(&x, &y);
+                assert!((value - expected.re).abs() < 1e-12 * expected.norm().max(1e-3), "Laplace m = {} {:?} {:?}", mode, x, y);
+                for k in [Complex64::new(3.0, 0.0), Complex64::new(2.0, 0.5)] {
+                    let helmholtz: Helmholtz = Helmholtz::new(k);
+                    let expected: Complex64 = ring(&x, &y, mode, &|a, b| PointKernel::<3>::eval(&helmholtz, a, b));
+                    let value: Complex64 = AxisymmetricHelmholtz::new(k, mode).eval(&x, &y);
+                    assert!((value - expected).norm() < 1e-11 * expected.norm().max(1e-3), "Helmholtz k = {} m = {} {:?} {:?}", k, mode, x, y);
+                }
+            }
+        }
+
+        // unit sphere from the semicircle rho = sin t, z = cos t: a unit density single layer is 1 inside for Laplace,
+        // e^{ik} sin(k|x|) / (k|x|) for Helmholtz. the kernels carry the rho' = sin t so integrate in u = cos t by
+        // Fejer's first rule, the midpoint rule in t is only second order at the poles
+        let n: usize = 60;
+        let t: Vec<f64> = (0..n).map(|j| PI * (j as f64 + 0.5) / n as f64).collect();
+        let weights: Vec<f64> = t.iter().map(|&t| {
+            let sum: f64 = (1..=n / 2).map(|l| (2.0 * l as f64 * t).cos() / (4.0 * (l * l) as f64 - 1.0)).sum();
+            2.0 * (1.0 - 2.0 * sum) / (n as f64 * t.sin())
+        }).collect();
+        let curve: Nodes<2> = Nodes::with_weights(t.iter().map(|t| [t.sin(), t.cos()]).collect(), weights);
+        let x: [f64; 2] = [0.3, 0.2];
+        let laplace: f64 = (0..n).map(|j| AxisymmetricLaplace::new(0).eval(&x, &curve.points[j]) * curve.weight(j)).sum();
+        assert!((laplace - 1.0).abs() < 1e-12);
+        let k: Complex64 = Complex64::new(2.5, 0.3);
+        let kr: Complex64 = k * (x[0] * x[0] + x[1] * x[1]).sqrt();
+        let helmholtz: Complex64 = (0..n).map(|j| AxisymmetricHelmholtz::new(k, 0).eval(&x, &curve.points[j]) * curve.weight(j)).sum();
+        assert!(close(helmholtz, (Complex64::i() * k).exp() * kr.sin() / kr, 1e-12));
+    }
+
+    #[test]
+    fn axisymmetric_self_terms() {
+        // eval_self minus the trapezoid log correction is the smooth part's limit: G_m(x, x + delta) + log(delta) / 2pi
+        let x: [f64; 2] = [0.7, 0.3];
+        let nodes: Nodes<2> = Nodes::with_weights(vec![x], vec![0.05]);
+        let delta: f64 = 1e-9;
+        let y: [f64; 2] = [x[0] + 0.6 * delta, x[1] + 0.8 * delta];
+        for mode in [0, 1, 3] {
+            let smooth: f64 = AxisymmetricLaplace::new(mode).eval_self(&nodes, 0).unwrap() - laplace_self_2d(&nodes, 0).unwrap();
+            let limit: f64 = AxisymmetricLaplace::new(mode).eval(&x, &y) + delta.ln() / (2.0 * PI);
+            assert!((smooth - limit).abs() < 1e-7, "m = {} {} {}", mode, smooth, limit);
+
+            let kernel: AxisymmetricHelmholtz = AxisymmetricHelmholtz::new(Complex64::new(3.0, 0.5), mode);
+            let smooth: Complex64 = kernel.eval_self(&nodes, 0).unwrap() - laplace_self_2d(&nodes, 0).unwrap();
+            let limit: Complex64 = kernel.eval(&x, &y) + delta.ln() / (2.0 * PI);
+            assert!((smooth - limit).norm() < 1e-7, "m = {} {} {}", mode, smooth, limit);
+        }
+
+        // on the axis: zero source rings, and a target there only sees m = 0
+        let axis: Nodes<2> = Nodes::with_weights(vec![[0.0, 1.0], [0.5, 0.2]], vec![0.1, 0.1]);
+        for mode in [0, 2] {
+            assert_eq!(AxisymmetricLaplace::new(mode).eval_nodes(&axis, 0, &axis, 0), 0.0);
+            assert_eq!(AxisymmetricLaplace::new(mode).eval_nodes(&axis, 1, &axis, 0), 0.0);
+            assert_eq!(AxisymmetricHelmholtz::new(2.0, mode).eval_nodes(&axis, 0, &axis, 0), Complex64::new(0.0, 0.0));
+            assert_eq!(AxisymmetricHelmholtz::new(2.0, mode).eval_nodes(&axis, 1, &axis, 0), Complex64::new(0.0, 0.0));
+        }
+        assert!(AxisymmetricLaplace::new(0).eval_nodes(&axis, 0, &axis, 1) > 0.0);
+        assert_eq!(AxisymmetricLaplace::new(2).eval_nodes(&axis, 0, &axis, 1), 0.0);
+        assert_eq!(AxisymmetricHelmholtz::new(2.0, 2).eval_nodes(&axis, 0, &axis, 1), Complex64::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn axisymmetric_nystrom_on_sphere() {
+        // on the unit sphere S[1] = 1 and S[sin t e^{i theta}] = sin t / 3 (Y_1^1 has eigenvalue 1 / (2l + 1)), for Helmholtz
+        // S[1] = e^{ik} sin k / k. midpoint rule in t on the semicircle with the self terms on the diagonal, second order
+        let error = |n: usize| -> f64 {
+            let t: Vec<f64> = (0..n).map(|j| PI * (j as f64 + 0.5) / n as f64).collect();
+            let curve: Nodes<2> = Nodes::with_weights(t.iter().map(|t| [t.sin(), t.cos()]).collect(), vec![PI / n as f64; n]);
+            let k: Complex64 = Complex64::new(2.5, 0.3);
+            let mut worst: f64 = 0.0;
+            for i in [n / 5, n / 2] {
+                let apply = |kernel: &dyn Fn(usize) -> Complex64, density: &dyn Fn(f64) -> f64| -> Complex64 {
+                    (0..n).map(|j| kernel(j) * curve.weight(j) * density(t[j])).sum()
+                };
+                let m0: Complex64 = apply(&|j| AxisymmetricLaplace::new(0).eval_nodes(&curve, i, &curve, j).into(), &|_| 1.0);
+                let m1: Complex64 = apply(&|j| AxisymmetricLaplace::new(1).eval_nodes(&curve, i, &curve, j).into(), &|t| t.sin());
+                let wave: Complex64 = apply(&|j| AxisymmetricHelmholtz::new(k, 0).eval_nodes(&curve, i, &curve, j), &|_| 1.0);
+                worst = worst.max((m0 - 1.0).norm()).max((m1 - t[i].sin() / 3.0).norm());
+                worst = worst.max((wave - (Complex64::i() * k).exp() * k.sin() / k).norm());
+            }
+            worst
+        };
+        let (coarse, fine): (f64, f64) = (error(100), error(400));
+        assert!(fine < 1e-5, "{} {}", coarse, fine);
+        assert!(fine < 0.1 * coarse, "{} {}", coarse, fine);
+    }
+}
@@ -4,9 +4,28 @@ pub mod cluster;
 pub mod block;
 pub mod hmatrix;
 pub mod functions;
+pub mod operator;
+pub mod trace;
+pub mod special;
+pub mod scalar;
+pub mod quadrature;
+pub mod tensor;
 
-pub use kernels::{Kernel, Laplace, Helmholtz};
+pub use kernels::{Kernel, PointKernel, ScalarKernel, point_eval_nodes, SelfTerm, Weighted, Laplace, Helmholtz, ModifiedHelmholtz};
+pub use kernels::{Gaussian, Matern, RationalQuadratic};
+pub use kernels::{LaplaceNormal, LaplaceAdjointNormal, LaplaceHypersingular, NormalProduct};
+pub use kernels::{HelmholtzNormal, HelmholtzAdjointNormal, HelmholtzHypersingular, CombinedField, QuasiPeriodicHelmholtz};
+pub use kernels::{KernelDk, PointKernelDk, Dk};
+pub use kernels::{Scaled, Sum, FnKernel};
+pub use kernels::{HalfSpace, Boundary};
+pub use kernels::{AxisymmetricLaplace, AxisymmetricHelmholtz};
+pub use scalar::Scalar;
 pub use nodes::{Nodes, BBox};
 pub use cluster::{ClusterNode, ClusterTree};
-pub use block::{BlockNode, BlockTree};
-pub use functions::cardioid_nodes;
\ No newline at end of file
+pub use block::{BlockNode, BlockTree, BlockType};
+pub use hmatrix::{HMatrix, BlockStorage, DenseBlock, LowRankBlock, BlockError};
+pub use operator::{LinearOperator, DenseMatrix, SumOp, ProductOp, ShiftedOp};
+pub use quadrature::{KapurRokhlin, ArcDerivative};
+pub use tensor::{TensorKernel, PointTensorKernel, Tensor, Stokeslet, Stresslet, Kelvin, Maxwell};
+pub use trace::{hutchinson_trace, hutchpp_trace, hutchinson_diagonal};
+pub use functions::{cardioid_nodes, cardioid_boundary, dense_matrix, approximation_error, ApproximationError};
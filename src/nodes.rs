@@ -1,10 +1,12 @@
 use distances::vectors::euclidean;
 
-// Nodes structure to hold points (and later boundary data in the case of dG problems)
+// Nodes structure to hold points and optional boundary data for Nystrom / double layer problems
+// weights and normals live here so they follow the same indices as points through clustering
 pub struct Nodes<const D: usize> {
     pub points: Vec<[f64; D]>,
-    // pub weights: Vec<[f64; 1]>,
-    // pub normals: Vec<[f64; D]>, 
+    pub weights: Option<Vec<f64>>, // quadrature weights, eg. arclength * dtheta on a curve
+    pub normals: Option<Vec<[f64; D]>>, // unit normals, outward by convention
+    pub curvatures: Option<Vec<f64>>, // signed curvature of a 2D curve, 1/R on a circle with outward normals
 }
 
 impl<const D: usize> Nodes<D> {
@@ -12,8 +14,66 @@ impl<const D: usize> Nodes<D> {
     // create Nodes from "standard" (Vec<[f64; D]>) points
     pub fn new(points: Vec<[f64; D]>) -> Self {
         assert!(!points.is_empty());
-        Self { points} 
-        // add method for weights and normals if/when dG implemented
+        Self { points, weights: None, normals: None, curvatures: None} 
+    }
+
+    pub fn with_weights(points: Vec<[f64; D]>, weights: Vec<f64>) -> Self {
+        Self::new(points).set_weights(weights)
+    }
+
+    pub fn with_normals(points: Vec<[f64; D]>, normals: Vec<[f64; D]>) -> Self {
+        Self::new(points).set_normals(normals)
+    }
+
+    // everything a boundary discretisation needs in one go
+    pub fn with_boundary(points: Vec<[f64; D]>, weights: Vec<f64>, normals: Vec<[f64; D]>) -> Self {
+        Self::new(points).set_weights(weights).set_normals(normals)
+    }
+
+    // builder style setters, lengths checked against points
+    pub fn set_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(weights.len(), self.points.len(), "need one weight per point");
+        self.weights = Some(weights);
+        self
+    }
+
+    pub fn set_normals(mut self, normals: Vec<[f64; D]>) -> Self {
+        assert_eq!(normals.len(), self.points.len(), "need one normal per point");
+        self.normals = Some(normals);
+        self
+    }
+
+    // only the 2D double layer diagonals read this, see double_layer_self_2d in kernels.rs
+    pub fn set_curvatures(mut self, curvatures: Vec<f64>) -> Self {
+        assert_eq!(curvatures.len(), self.points.len(), "need one curvature per point");
+        self.curvatures = Some(curvatures);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // quadrature weight of node i, 1 if no weights were given (plain collocation)
+    pub fn weight(&self, i: usize) -> f64 {
+        match &self.weights {
+            Some(weights) => weights[i],
+            None => 1.0,
+        }
+    }
+
+    // normal at node i, None if no normals were given
+    pub fn normal(&self, i: usize) -> Option<&[f64; D]> {
+        self.normals.as_ref().map(|normals| &normals[i])
+    }
+
+    // curvature at node i, None if none was given
+    pub fn curvature(&self, i: usize) -> Option<f64> {
+        self.curvatures.as_ref().map(|curvatures| curvatures[i])
     }
 
     // creating bounding boxes from indices allows the reuse of Nodes structure instead of storing subdivisions of Nodes.points
@@ -54,6 +114,40 @@ mod node_struct_test { // all need different names for each struct
         assert_eq!(4, 4); // needs to return a bool 
     }
 
+    #[test]
+    fn boundary_data() {
+        let points = vec![[0.0, 0.0], [1.0, 0.0]];
+        let plain = Nodes::new(points.clone());
+        assert_eq!(plain.weight(1), 1.0);
+        assert!(plain.normal(0).is_none());
+
+        let nodes = Nodes::with_boundary(points, vec![0.5, 0.25], vec![[0.0, 1.0], [1.0, 0.0]]);
+        assert_eq!(nodes.weight(1), 0.25);
+        assert_eq!(nodes.normal(0), Some(&[0.0, 1.0]));
+        assert!(nodes.curvature(0).is_none());
+
+        let curved = Nodes::with_normals(vec![[1.0, 0.0]], vec![[1.0, 0.0]]).set_curvatures(vec![1.0]);
+        assert_eq!(curved.curvature(0), Some(1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "need one weight per point")]
+    fn weights_wrong_length() {
+        let _nodes = Nodes::with_weights(vec![[0.0, 0.0], [1.0, 0.0]], vec![1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "need one normal per point")]
+    fn normals_wrong_length() {
+        let _nodes = Nodes::with_normals(vec![[0.0, 0.0], [1.0, 0.0]], vec![[0.0, 1.0]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "need one curvature per point")]
+    fn curvatures_wrong_length() {
+        let _nodes = Nodes::new(vec![[0.0, 0.0], [1.0, 0.0]]).set_curvatures(vec![1.0, 1.0, 1.0]);
+    }
+
     // add more tests here
 }
 
@@ -70,19 +164,29 @@ impl <const D: usize> BBox<D> {
     pub fn centre(&self) -> Vec<f64>{ 
 
         let mut centre: Vec<f64> = Vec::with_capacity(D);
-        let dim: f64 = D as f64;
 
         for d in 0..D {
-            let centre_i: f64 = (self.min[d] + self.max[d])/ dim ;
+            let centre_i: f64 = (self.min[d] + self.max[d]) / 2.0; // midpoint, was / D which only worked in 2D
             centre.push(centre_i);
         }
         centre 
     }
 
+    // length of the box diagonal
+    pub fn diameter(&self) -> f64 {
+        (0..D).map(|d| (self.max[d] - self.min[d]).powi(2)).sum::<f64>().sqrt()
+    }
+
+    // smallest distance between any two points of the boxes, 0 if they overlap
+    pub fn gap(source_bbox: &BBox<D>, target_bbox: &BBox<D>) -> f64 {
+        (0..D).map(|d| {
+            let apart: f64 = (source_bbox.min[d] - target_bbox.max[d]).max(target_bbox.min[d] - source_bbox.max[d]).max(0.0);
+            apart * apart
+        }).sum::<f64>().sqrt()
+    }
+
     pub fn prox_dims(&self) -> Vec<f64> {
-        let mut test = Vec::new();
-        test.push(2.3);
-        test.push(1.2);
+        let test = vec![2.3, 1.2];
         test
     }
 
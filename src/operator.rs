@@ -0,0 +1,305 @@
+use crate::kernels::Kernel;
+use crate::scalar::Scalar;
+use crate::hmatrix::{HMatrix, BlockStorage, DenseBlock, LowRankBlock};
+
+// anything that can do y = Ax and x = A^H y -- solvers, eigensolvers etc. should only ever need this
+pub trait LinearOperator {
+    type Scalar: Scalar;
+    fn nrows(&self) -> usize;
+    fn ncols(&self) -> usize;
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar>; // len(x) == ncols, returns nrows
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar>; // len(y) == nrows, returns ncols
+}
+
+// borrowing an operator is as good as owning it, lets the combinators below take references
+impl<T: LinearOperator + ?Sized> LinearOperator for &T {
+    type Scalar = T::Scalar;
+    fn nrows(&self) -> usize { (**self).nrows() }
+    fn ncols(&self) -> usize { (**self).ncols() }
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> { (**self).apply(x) }
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> { (**self).apply_adjoint(y) }
+}
+
+// ---------------- BLOCKS AND HMATRIX ----------------------
+
+// blocks act on vectors in their own local numbering ie. len(rows) and len(cols)
+impl<T: Scalar> LinearOperator for DenseBlock<T> {
+    type Scalar = T;
+    fn nrows(&self) -> usize { self.rows.len() }
+    fn ncols(&self) -> usize { self.cols.len() }
+
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(x.len(), self.ncols());
+        let n: usize = self.cols.len();
+        (0..self.rows.len())
+            .map(|i| self.data[i * n..(i + 1) * n].iter().zip(x).map(|(&a, &b)| a * b).sum())
+            .collect()
+    }
+
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(y.len(), self.nrows());
+        let n: usize = self.cols.len();
+        let mut x: Vec<T> = vec![T::zero(); n];
+        for (i, yi) in y.iter().enumerate() {
+            for (xj, a) in x.iter_mut().zip(&self.data[i * n..(i + 1) * n]) {
+                *xj += a.conj() * *yi;
+            }
+        }
+        x
+    }
+}
+
+// UV^T x done as U (V^T x) so it only costs rank * (m + n), plus the sparse corrections
+impl<T: Scalar> LinearOperator for LowRankBlock<T> {
+    type Scalar = T;
+    fn nrows(&self) -> usize { self.rows.len() }
+    fn ncols(&self) -> usize { self.cols.len() }
+
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(x.len(), self.ncols());
+        let k: usize = self.rank;
+        let mut vtx: Vec<T> = vec![Self::Scalar::zero(); k];
+        for (j, xj) in x.iter().enumerate() {
+            for (t, v) in vtx.iter_mut().zip(&self.v[j * k..(j + 1) * k]) { *t += *v * *xj; }
+        }
+        let mut y: Vec<T> = (0..self.rows.len())
+            .map(|i| self.u[i * k..(i + 1) * k].iter().zip(&vtx).map(|(&u, &t)| u * t).sum())
+            .collect();
+        for (&(i, j), &delta) in &self.corrections { y[i] += delta * x[j]; }
+        y
+    }
+
+    // (UV^T)^H = conj(V) U^H
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(y.len(), self.nrows());
+        let k: usize = self.rank;
+        let mut uhy: Vec<T> = vec![Self::Scalar::zero(); k];
+        for (i, yi) in y.iter().enumerate() {
+            for (t, u) in uhy.iter_mut().zip(&self.u[i * k..(i + 1) * k]) { *t += u.conj() * *yi; }
+        }
+        let mut x: Vec<T> = (0..self.cols.len())
+            .map(|j| self.v[j * k..(j + 1) * k].iter().zip(&uhy).map(|(v, &t)| v.conj() * t).sum())
+            .collect();
+        for (&(i, j), &delta) in &self.corrections { x[j] += delta.conj() * y[i]; }
+        x
+    }
+}
+
+impl<T: Scalar> LinearOperator for BlockStorage<T> {
+    type Scalar = T;
+    fn nrows(&self) -> usize {
+        match self { BlockStorage::Dense(b) => b.nrows(), BlockStorage::LowRank(b) => b.nrows() }
+    }
+    fn ncols(&self) -> usize {
+        match self { BlockStorage::Dense(b) => b.ncols(), BlockStorage::LowRank(b) => b.ncols() }
+    }
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        match self { BlockStorage::Dense(b) => b.apply(x), BlockStorage::LowRank(b) => b.apply(x) }
+    }
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        match self { BlockStorage::Dense(b) => b.apply_adjoint(y), BlockStorage::LowRank(b) => b.apply_adjoint(y) }
+    }
+}
+
+// gather x onto each leaf's columns, apply the leaf, scatter back onto its rows
+impl<const D: usize, K: Kernel<D>> LinearOperator for HMatrix<D, K> {
+    type Scalar = K::Scalar;
+    fn nrows(&self) -> usize { self.n_rows }
+    fn ncols(&self) -> usize { self.n_cols }
+
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(x.len(), self.n_cols);
+        let mut y: Vec<K::Scalar> = vec![Self::Scalar::zero(); self.n_rows];
+        for block in &self.blocks {
+            let (rows, cols) = (block.rows(), block.cols());
+            let local_x: Vec<K::Scalar> = cols.iter().map(|&j| x[j]).collect();
+            for (&i, yi) in rows.iter().zip(block.apply(&local_x)) { y[i] += yi; }
+        }
+        y
+    }
+
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(y.len(), self.n_rows);
+        let mut x: Vec<K::Scalar> = vec![Self::Scalar::zero(); self.n_cols];
+        for block in &self.blocks {
+            let (rows, cols) = (block.rows(), block.cols());
+            let local_y: Vec<K::Scalar> = rows.iter().map(|&i| y[i]).collect();
+            for (&j, xj) in cols.iter().zip(block.apply_adjoint(&local_y)) { x[j] += xj; }
+        }
+        x
+    }
+}
+
+// ---------------- PLAIN DENSE MATRIX ----------------------
+
+// full matrix for comparisons, row major like everything else -- eg. DenseMatrix::new(n, n, hmat.to_dense())
+pub struct DenseMatrix<T: Scalar> {
+    pub n_rows: usize,
+    pub n_cols: usize,
+    pub data: Vec<T>,
+}
+
+impl<T: Scalar> DenseMatrix<T> {
+    pub fn new(n_rows: usize, n_cols: usize, data: Vec<T>) -> Self {
+        assert_eq!(data.len(), n_rows * n_cols, "data must be n_rows * n_cols");
+        Self { n_rows, n_cols, data }
+    }
+}
+
+impl<T: Scalar> LinearOperator for DenseMatrix<T> {
+    type Scalar = T;
+    fn nrows(&self) -> usize { self.n_rows }
+    fn ncols(&self) -> usize { self.n_cols }
+
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(x.len(), self.n_cols);
+        let n: usize = self.n_cols;
+        (0..self.n_rows)
+            .map(|i| self.data[i * n..(i + 1) * n].iter().zip(x).map(|(&a, &b)| a * b).sum())
+            .collect()
+    }
+
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        assert_eq!(y.len(), self.n_rows);
+        let n: usize = self.n_cols;
+        let mut x: Vec<T> = vec![T::zero(); n];
+        for (i, yi) in y.iter().enumerate() {
+            for (xj, a) in x.iter_mut().zip(&self.data[i * n..(i + 1) * n]) { *xj += a.conj() * *yi; }
+        }
+        x
+    }
+}
+
+// ---------------- COMBINATORS ----------------------
+
+// A + B
+pub struct SumOp<A, B> { pub a: A, pub b: B }
+
+impl<A: LinearOperator, B: LinearOperator<Scalar = A::Scalar>> SumOp<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        assert!(a.nrows() == b.nrows() && a.ncols() == b.ncols(), "summed operators must have the same shape");
+        Self { a, b }
+    }
+}
+
+impl<A: LinearOperator, B: LinearOperator<Scalar = A::Scalar>> LinearOperator for SumOp<A, B> {
+    type Scalar = A::Scalar;
+    fn nrows(&self) -> usize { self.a.nrows() }
+    fn ncols(&self) -> usize { self.a.ncols() }
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        self.a.apply(x).iter().zip(self.b.apply(x)).map(|(&p, q)| p + q).collect()
+    }
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        self.a.apply_adjoint(y).iter().zip(self.b.apply_adjoint(y)).map(|(&p, q)| p + q).collect()
+    }
+}
+
+// AB, applied right to left
+pub struct ProductOp<A, B> { pub a: A, pub b: B }
+
+impl<A: LinearOperator, B: LinearOperator<Scalar = A::Scalar>> ProductOp<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        assert_eq!(a.ncols(), b.nrows(), "inner dimensions of a product must agree");
+        Self { a, b }
+    }
+}
+
+impl<A: LinearOperator, B: LinearOperator<Scalar = A::Scalar>> LinearOperator for ProductOp<A, B> {
+    type Scalar = A::Scalar;
+    fn nrows(&self) -> usize { self.a.nrows() }
+    fn ncols(&self) -> usize { self.b.ncols() }
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> { self.a.apply(&self.b.apply(x)) }
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> { self.b.apply_adjoint(&self.a.apply_adjoint(y)) }
+}
+
+// A + shift * I, the usual (A - zI) for eigen and resolvent stuff is ShiftedOp::new(a, -z)
+pub struct ShiftedOp<A: LinearOperator> { pub op: A, pub shift: A::Scalar }
+
+impl<A: LinearOperator> ShiftedOp<A> {
+    pub fn new(op: A, shift: A::Scalar) -> Self {
+        assert_eq!(op.nrows(), op.ncols(), "only square operators can be shifted");
+        Self { op, shift }
+    }
+}
+
+impl<A: LinearOperator> LinearOperator for ShiftedOp<A> {
+    type Scalar = A::Scalar;
+    fn nrows(&self) -> usize { self.op.nrows() }
+    fn ncols(&self) -> usize { self.op.ncols() }
+    fn apply(&self, x: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        self.op.apply(x).iter().zip(x).map(|(&ax, &xi)| ax + self.shift * xi).collect()
+    }
+    fn apply_adjoint(&self, y: &[Self::Scalar]) -> Vec<Self::Scalar> {
+        let shift: A::Scalar = self.shift.conj();
+        self.op.apply_adjoint(y).iter().zip(y).map(|(&ay, &yi)| ay + shift * yi).collect()
+    }
+}
+
+#[cfg(test)]
+mod operator_tests {
+    use super::*;
+    use crate::functions::cardioid_hmatrix;
+    use crate::hmatrix::ACA_TOL;
+
+    use num_complex::Complex64;
+
+    fn test_vector(n: usize, seed: f64) -> Vec<f64> {
+        (0..n).map(|i| (seed * i as f64).sin() + (seed + i as f64).cos()).collect()
+    }
+
+    fn max_diff<T: Scalar>(a: &[T], b: &[T]) -> f64 {
+        a.iter().zip(b).map(|(&p, &q)| (p - q).abs()).fold(0.0, f64::max)
+    }
+
+    fn dot<T: Scalar>(a: &[T], b: &[T]) -> T {
+        a.iter().zip(b).map(|(p, &q)| p.conj() * q).sum()
+    }
+
+    #[test]
+    fn hmatrix_matches_dense() {
+        let (_, hmat) = cardioid_hmatrix(90, ACA_TOL);
+        let dense: DenseMatrix<f64> = DenseMatrix::new(90, 90, hmat.to_dense());
+        let x: Vec<f64> = test_vector(90, 0.3);
+        assert!(max_diff(&hmat.apply(&x), &dense.apply(&x)) < 1e-10);
+        assert!(max_diff(&hmat.apply_adjoint(&x), &dense.apply_adjoint(&x)) < 1e-10);
+    }
+
+    #[test]
+    fn adjoint_identity() {
+        let (_, hmat) = cardioid_hmatrix(70, ACA_TOL);
+        let x: Vec<f64> = test_vector(70, 0.7);
+        let y: Vec<f64> = test_vector(70, 1.9);
+        let lhs: f64 = dot(&y, &hmat.apply(&x));
+        let rhs: f64 = dot(&hmat.apply_adjoint(&y), &x);
+        assert!((lhs - rhs).abs() < 1e-10 * lhs.abs());
+    }
+
+    #[test]
+    fn combinators_match_dense() {
+        let (_, hmat) = cardioid_hmatrix(40, ACA_TOL);
+        let dense: DenseMatrix<f64> = DenseMatrix::new(40, 40, hmat.to_dense());
+        let x: Vec<f64> = test_vector(40, 1.1);
+        let ax: Vec<f64> = dense.apply(&x);
+        let shift: f64 = -2.0;
+
+        let sum = SumOp::new(&hmat, &dense);
+        let twice: Vec<f64> = ax.iter().map(|z| 2.0 * z).collect();
+        assert!(max_diff(&sum.apply(&x), &twice) < 1e-10);
+
+        let product = ProductOp::new(&hmat, &dense);
+        assert!(max_diff(&product.apply(&x), &dense.apply(&ax)) < 1e-8);
+
+        let shifted = ShiftedOp::new(&hmat, shift);
+        let expected: Vec<f64> = ax.iter().zip(&x).map(|(a, xi)| a + shift * xi).collect();
+        assert!(max_diff(&shifted.apply(&x), &expected) < 1e-10);
+
+        // complex shift on a complex copy, the adjoint has to conjugate it
+        let complex: DenseMatrix<Complex64> = DenseMatrix::new(40, 40, dense.data.iter().map(|a| a.to_complex()).collect());
+        let shifted = ShiftedOp::new(&complex, Complex64::new(0.5, -2.0));
+        let xc: Vec<Complex64> = x.iter().map(|&a| Complex64::new(a, 0.3 * a)).collect();
+        let yc: Vec<Complex64> = test_vector(40, 2.3).iter().map(|&a| Complex64::new(-a, a)).collect();
+        let lhs: Complex64 = dot(&yc, &shifted.apply(&xc));
+        let rhs: Complex64 = dot(&shifted.apply_adjoint(&yc), &xc);
+        assert!((lhs - rhs).norm() < 1e-10 * lhs.norm());
+    }
+}
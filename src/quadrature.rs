@@ -0,0 +1,334 @@
+use std::marker::PhantomData;
+use crate::kernels::ScalarKernel;
+use crate::hmatrix::HMatrix;
+use crate::nodes::Nodes;
+use crate::operator::LinearOperator;
+use crate::scalar::Scalar;
+
+// near field corrections applied after assembly, for log singular kernels on a single closed 2D curve
+// assumes node i sits at t_i = 2 pi i / n on the parametrisation (eg. cardioid_boundary) and the matrix is the
+// Nystrom one, ie. built from Weighted<K> so column j already carries the arclength weight w_j
+
+// Kapur-Rokhlin corrected trapezoid rule for f(s) = phi(s) log|s - t| + psi(s): drop the singular node and scale
+// the m neighbours either side by 1 + gamma_l. coefficients from Kapur & Rokhlin (1997), as tabulated in
+// Hao, Barnett, Martinsson & Young (2014)
+const KR_2: [f64; 2] = [1.825748064736159, -1.325748064736159];
+const KR_6: [f64; 6] = [4.967362978287758, -16.20501504859126, 25.85153761832639, -22.22599466791883,
+    9.930104998037539, -1.817995878141594];
+const KR_10: [f64; 10] = [7.832432020568779, -45.65161670374749, 145.2168846354677, -290.1348302886379,
+    387.08621625799, -352.3821383570681, 217.2421547519342, -87.07796087382991, 20.53584266072635,
+    -2.166984103403823];
+
+pub struct KapurRokhlin { pub order: usize, pub gammas: &'static [f64] }
+
+impl KapurRokhlin {
+    // order 2, 6 or 10 -- error goes like h^order (up to logs) for smooth curves and densities
+    pub fn new(order: usize) -> Self {
+        let gammas: &'static [f64] = match order {
+            2 => &KR_2,
+            6 => &KR_6,
+            10 => &KR_10,
+            _ => panic!("Kapur-Rokhlin rules only tabulated for orders 2, 6 and 10, got {}", order),
+        };
+        Self { order, gammas }
+    }
+
+    // what entry A_ij gets multiplied by, nodes are n points round the curve so offsets wrap
+    pub fn factor(&self, i: usize, j: usize, n: usize) -> f64 {
+        let offset: usize = i.abs_diff(j);
+        let offset: usize = offset.min(n - offset);
+        if offset == 0 { 0.0 } // punctured trapezoid, singular node dropped
+        else if offset <= self.gammas.len() { 1.0 + self.gammas[offset - 1] }
+        else { 1.0 }
+    }
+
+    // correct an assembled HMatrix in place -- the touched entries are near the diagonal so they are nearly always in
+    // dense leaves, anything that landed in a low rank leaf becomes one of its sparse corrections (see HMatrix::set)
+    // the entries are scaled, not overwritten, so this runs exactly once per matrix and panics on a second go
+    pub fn correct<const D: usize, K: ScalarKernel<D>>(&self, hmatrix: &mut HMatrix<D, K>) {
+        assert!(!hmatrix.quadrature_corrected, "Kapur-Rokhlin correction already applied to this HMatrix");
+        hmatrix.quadrature_corrected = true;
+        let n: usize = hmatrix.n_rows;
+        assert_eq!(n, hmatrix.n_cols, "Kapur-Rokhlin needs targets == sources");
+        assert!(n > 2 * self.gammas.len(), "need more than {} nodes on the curve for order {}", 2 * self.gammas.len(), self.order);
+        let m: usize = self.gammas.len();
+
+        for i in 0..n {
+            for l in 0..=2 * m {
+                let j: usize = (i + n + l - m) % n;
+                let value: K::Scalar = hmatrix.get(i, j);
+                hmatrix.set(i, j, value * K::Scalar::from_real(self.factor(i, j, n)));
+            }
+        }
+    }
+
+    // same thing on a row major n x n dense matrix, eg. from dense_matrix. nothing here records that it ran, so call it
+    // exactly once on freshly assembled data
+    pub fn correct_dense<T: Scalar>(&self, data: &mut [T], n: usize) {
+        assert_eq!(data.len(), n * n, "data must be n x n");
+        assert!(n > 2 * self.gammas.len(), "need more than {} nodes on the curve for order {}", 2 * self.gammas.len(), self.order);
+        let m: usize = self.gammas.len();
+
+        for i in 0..n {
+            for l in 0..=2 * m {
+                let j: usize = (i + n + l - m) % n;
+                data[i * n + j] *= T::from_real(self.factor(i, j, n));
+            }
+        }
+    }
+}
+
+// d/ds on the same nodes: spectral derivative in t of the trigonometric interpolant, divided by the speed |x'(t_i)|
+// (read off the weights, w_i = |x'(t_i)| 2pi/n). with a corrected S this gives the Maue form of the 2D hypersingular
+// operator, W = ProductOp(d/ds, ProductOp(S, d/ds)) + k^2 S_n, see NormalProduct. dense n^2 apply, fine next to S
+pub struct ArcDerivative<T> { pub speeds: Vec<f64>, scalar: PhantomData<T> }
+
+impl<T: Scalar> ArcDerivative<T> {
+    pub fn new(nodes: &Nodes<2>) -> Self {
+        assert!(nodes.weights.is_some(), "ArcDerivative needs trapezoid weights on the Nodes");
+        let n: usize = nodes.len();
+        let speeds: Vec<f64> = (0..n).map(|i| nodes.weight(i) * n as f64 / (2.0 * std::f64::consts::PI)).collect();
+        assert!(speeds.iter().all(|&speed| speed > 0.0), "ArcDerivative needs a regular curve, got a zero weight (eg. a cusp)");
+        Self { speeds, scalar: PhantomData }
+    }
+
+    // d/dt weights for an offset of i - j nodes: (-1)^(i-j) cot((i-j) h/2) / 2 for even n, csc instead of cot for odd
+    fn entry(&self, offset: usize) -> f64 {
+        let n: usize = self.speeds.len();
+        if offset == 0 { return 0.0 }
+        let half_angle: f64 = std::f64::consts::PI * offset as f64 / n as f64;
+        let sign: f64 = if offset.is_multiple_of(2) { 1.0 } else { -1.0 };
+        if n.is_multiple_of(2) { 0.5 * sign / half_angle.tan() } else { 0.5 * sign / half_angle.sin() }
+    }
+}
+
+impl<T: Scalar> LinearOperator for ArcDerivative<T> {
+    type Scalar = T;
+    fn nrows(&self) -> usize { self.speeds.len() }
+    fn ncols(&self) -> usize { self.speeds.len() }
+
+    fn apply(&self, x: &[T]) -> Vec<T> {
+        let n: usize = self.speeds.len();
+        assert_eq!(x.len(), n);
+        (0..n).map(|i| {
+            let dt: T = (0..n).map(|j| x[j] * T::from_real(self.entry((i + n - j) % n))).fold(T::zero(), |a, b| a + b);
+            dt * T::from_real(1.0 / self.speeds[i])
+        }).collect()
+    }
+
+    // the t derivative matrix is real and antisymmetric, so (S^-1 D)^H = -D S^-1
+    fn apply_adjoint(&self, y: &[T]) -> Vec<T> {
+        let n: usize = self.speeds.len();
+        assert_eq!(y.len(), n);
+        let scaled: Vec<T> = y.iter().zip(&self.speeds).map(|(&v, &speed)| v * T::from_real(1.0 / speed)).collect();
+        (0..n).map(|i| {
+            -(0..n).map(|j| scaled[j] * T::from_real(self.entry((i + n - j) % n))).fold(T::zero(), |a, b| a + b)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod quadrature_tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use num_complex::Complex64;
+    use crate::kernels::{Helmholtz, HelmholtzNormal, HelmholtzHypersingular, Laplace, LaplaceHypersingular, PointKernel, Weighted};
+    use crate::operator::{DenseMatrix, ProductOp, SumOp};
+    use crate::special::hankel1_01;
+    use crate::nodes::Nodes;
+    use crate::cluster::ClusterTree;
+    use crate::block::BlockTree;
+    use crate::operator::LinearOperator;
+    use crate::functions::dense_matrix;
+
+    // ellipse with semi axes a, b, trapezoid arclength weights
+    fn ellipse(n: usize, a: f64, b: f64) -> Nodes<2> {
+        let h: f64 = 2.0 * PI / n as f64;
+        let mut points: Vec<[f64; 2]> = Vec::with_capacity(n);
+        let mut weights: Vec<f64> = Vec::with_capacity(n);
+        let mut normals: Vec<[f64; 2]> = Vec::with_capacity(n);
+        let mut curvatures: Vec<f64> = Vec::with_capacity(n);
+        for i in 0..n {
+            let t: f64 = h * i as f64;
+            let speed: f64 = (a * a * t.sin().powi(2) + b * b * t.cos().powi(2)).sqrt();
+            points.push([a * t.cos(), b * t.sin()]);
+            weights.push(speed * h);
+            normals.push([b * t.cos() / speed, a * t.sin() / speed]);
+            curvatures.push(a * b / speed.powi(3));
+        }
+        Nodes::with_boundary(points, weights, normals).set_curvatures(curvatures)
+    }
+
+    // gaussian elimination with partial pivoting on a row major n x n system, small test problems only
+    fn solve(mut a: Vec<Complex64>, mut b: Vec<Complex64>) -> Vec<Complex64> {
+        let n: usize = b.len();
+        for col in 0..n {
+            let pivot: usize = (col..n).max_by(|&p, &q| a[p * n + col].norm().total_cmp(&a[q * n + col].norm())).unwrap();
+            for k in 0..n { a.swap(col * n + k, pivot * n + k); }
+            b.swap(col, pivot);
+            for row in col + 1..n {
+                let factor: Complex64 = a[row * n + col] / a[col * n + col];
+                for k in col..n { a[row * n + k] = a[row * n + k] - factor * a[col * n + k]; }
+                b[row] = b[row] - factor * b[col];
+            }
+        }
+        for row in (0..n).rev() {
+            let tail: Complex64 = (row + 1..n).map(|k| a[row * n + k] * b[k]).sum();
+            b[row] = (b[row] - tail) / a[row * n + row];
+        }
+        b
+    }
+
+    // exterior Dirichlet on an ellipse with u = i/4 H0(k|x - x0|), x0 inside, through the Brakhage-Werner
+    // ansatz u = (D - i eta S) phi: solve (I/2 + D - i eta S) phi = u on the curve and return the worst error
+    // at a few points outside. D is plain trapezoid with the curvature diagonal (the r^2 log r in H1 keeps it short of
+    // spectral for k != 0, but it is still ~h^8 here), S is Kapur-Rokhlin corrected
+    fn combined_field_error(n: usize, zero_diagonal: bool) -> f64 {
+        let (k, eta): (f64, f64) = (2.0, 2.0);
+        let nodes: Nodes<2> = ellipse(n, 2.0, 1.0);
+        let source: [f64; 2] = [0.3, 0.2];
+        let exact = |x: &[f64; 2]| Helmholtz::new(k).eval(x, &source);
+
+        let double: Vec<Complex64> = dense_matrix(&nodes, &nodes, &Weighted::new(HelmholtzNormal::new(k)));
+        let mut single: Vec<Complex64> = dense_matrix(&nodes, &nodes, &Weighted::new(Helmholtz::new(k)));
+        KapurRokhlin::new(10).correct_dense(&mut single, n);
+        let matrix: Vec<Complex64> = (0..n * n).map(|ij| {
+            let diagonal: bool = ij / n == ij % n;
+            let d: Complex64 = if diagonal && zero_diagonal { Complex64::from(0.0) } else { double[ij] };
+            d - Complex64::i() * eta * single[ij] + if diagonal { Complex64::from(0.5) } else { Complex64::from(0.0) }
+        }).collect();
+        let phi: Vec<Complex64> = solve(matrix, nodes.points.iter().map(exact).collect());
+
+        let outside: Nodes<2> = Nodes::new(vec![[3.0, 1.0], [-1.0, 2.5], [0.5, -1.5]]);
+        let d_out: Vec<Complex64> = dense_matrix(&outside, &nodes, &Weighted::new(HelmholtzNormal::new(k)));
+        let s_out: Vec<Complex64> = dense_matrix(&outside, &nodes, &Weighted::new(Helmholtz::new(k)));
+        (0..outside.len()).map(|p| {
+            let u: Complex64 = (0..n).map(|j| (d_out[p * n + j] - Complex64::i() * eta * s_out[p * n + j]) * phi[j]).sum();
+            (u - exact(&outside.points[p])).norm()
+        }).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn combined_field_solve_converges() {
+        let errors: Vec<f64> = [32, 64, 128].iter().map(|&n| combined_field_error(n, false)).collect();
+        assert!(errors[2] < 1e-6, "{:?}", errors);
+        assert!(errors[1] < 1e-2 * errors[0] && errors[2] < 1e-2 * errors[1], "{:?}", errors);
+
+        // dropping the curvature limit leaves an O(h) error in every row
+        let zeroed: f64 = combined_field_error(128, true);
+        assert!(zeroed > 1e2 * errors[2], "{} vs {:?}", zeroed, errors);
+    }
+
+    fn single_layer(nodes: &Nodes<2>, rule: Option<&KapurRokhlin>, density: impl Fn(f64) -> f64) -> Vec<f64> {
+        let n: usize = nodes.len();
+        let tree: ClusterTree<2> = ClusterTree::build_tree(nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let mut hmat = HMatrix::assemble_with_tol(nodes, nodes, &tree, &tree, blocks, Weighted::new(Laplace), 1e-13);
+        if let Some(rule) = rule { rule.correct(&mut hmat); }
+        let phi: Vec<f64> = (0..n).map(|i| density(2.0 * PI * i as f64 / n as f64)).collect();
+        hmat.apply(&phi)
+    }
+
+    #[test]
+    fn circle_single_layer_high_order() {
+        // S[cos 3t] = cos 3t / 6 on the unit circle
+        let n: usize = 128;
+        let nodes: Nodes<2> = ellipse(n, 1.0, 1.0);
+        let max_err = |values: Vec<f64>| -> f64 {
+            values.iter().enumerate().map(|(i, v)| (v - (3.0 * 2.0 * PI * i as f64 / n as f64).cos() / 6.0).abs()).fold(0.0, f64::max)
+        };
+        let diagonal_only: f64 = max_err(single_layer(&nodes, None, |t| (3.0 * t).cos()));
+        let corrected: f64 = max_err(single_layer(&nodes, Some(&KapurRokhlin::new(10)), |t| (3.0 * t).cos()));
+        assert!(corrected < 1e-8, "{}", corrected);
+        assert!(corrected < 1e-2 * diagonal_only);
+    }
+
+    #[test]
+    #[should_panic(expected = "already applied")]
+    fn correct_twice_panics() {
+        let nodes: Nodes<2> = ellipse(64, 2.0, 1.0);
+        let tree: ClusterTree<2> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let mut hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, Weighted::new(Laplace));
+        let rule: KapurRokhlin = KapurRokhlin::new(6);
+        rule.correct(&mut hmat);
+        assert!(hmat.quadrature_corrected);
+        rule.correct(&mut hmat);
+    }
+
+    #[test]
+    fn ellipse_converges_and_matches_dense() {
+        let rule: KapurRokhlin = KapurRokhlin::new(10);
+        let density = |t: f64| 1.0 + 0.5 * t.sin();
+        let coarse: Vec<f64> = single_layer(&ellipse(128, 2.0, 1.0), Some(&rule), density);
+        let fine: Vec<f64> = single_layer(&ellipse(512, 2.0, 1.0), Some(&rule), density);
+        let diff: f64 = coarse.iter().enumerate().map(|(i, c)| (c - fine[4 * i]).abs()).fold(0.0, f64::max);
+        assert!(diff < 1e-6, "{}", diff);
+
+        // HMatrix path agrees with correcting the dense matrix directly
+        let nodes: Nodes<2> = ellipse(128, 2.0, 1.0);
+        let mut dense: Vec<f64> = dense_matrix(&nodes, &nodes, &Weighted::new(Laplace));
+        rule.correct_dense(&mut dense, 128);
+        for (i, c) in coarse.iter().enumerate() {
+            let row: f64 = (0..128).map(|j| dense[i * 128 + j] * density(2.0 * PI * j as f64 / 128.0)).sum();
+            assert!((row - c).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn arc_derivative_is_spectral() {
+        // d/ds of sin(2t) + cos(3t) on the ellipse is (2cos 2t - 3 sin 3t) / |x'(t)|
+        let nodes: Nodes<2> = ellipse(40, 2.0, 1.0);
+        let derivative: ArcDerivative<f64> = ArcDerivative::new(&nodes);
+        let t = |i: usize| 2.0 * PI * i as f64 / 40.0;
+        let f: Vec<f64> = (0..40).map(|i| (2.0 * t(i)).sin() + (3.0 * t(i)).cos()).collect();
+        for (i, value) in derivative.apply(&f).iter().enumerate() {
+            let exact: f64 = (2.0 * (2.0 * t(i)).cos() - 3.0 * (3.0 * t(i)).sin()) / derivative.speeds[i];
+            assert!((value - exact).abs() < 1e-12);
+        }
+
+        // <Dx, y> = <x, D^H y>
+        let y: Vec<f64> = (0..40).map(|i| (t(i) * 5.0).sin() * t(i)).collect();
+        let lhs: f64 = derivative.apply(&f).iter().zip(&y).map(|(a, b)| a * b).sum();
+        let rhs: f64 = f.iter().zip(derivative.apply_adjoint(&y)).map(|(a, b)| a * b).sum();
+        assert!((lhs - rhs).abs() < 1e-10);
+    }
+
+    // Kapur-Rokhlin corrected Nystrom matrix of a kernel as an operator
+    fn corrected<K: ScalarKernel<2>>(nodes: &Nodes<2>, kernel: K) -> DenseMatrix<K::Scalar> {
+        let n: usize = nodes.len();
+        let mut data: Vec<K::Scalar> = dense_matrix(nodes, nodes, &Weighted::new(kernel));
+        KapurRokhlin::new(10).correct_dense(&mut data, n);
+        DenseMatrix::new(n, n, data)
+    }
+
+    #[test]
+    fn maue_hypersingular_on_circle() {
+        // e^{imt} is an eigenfunction on the unit circle (the derivatives amplify the h^10 Kapur-Rokhlin error by ~n): -|m|/2 for Laplace, (i pi k^2 / 2) J_m'(k) H_m'(k) for Helmholtz
+        let n: usize = 128;
+        let nodes: Nodes<2> = ellipse(n, 1.0, 1.0);
+        let t = |i: usize| 2.0 * PI * i as f64 / n as f64;
+
+        let derivative: ArcDerivative<f64> = ArcDerivative::new(&nodes);
+        let single: DenseMatrix<f64> = corrected(&nodes, LaplaceHypersingular.maue());
+        let w = ProductOp::new(&derivative, ProductOp::new(&single, &derivative));
+        let phi: Vec<f64> = (0..n).map(|i| (3.0 * t(i)).cos()).collect();
+        for (i, value) in w.apply(&phi).iter().enumerate() {
+            assert!((value + 1.5 * phi[i]).abs() < 1e-7, "{} {}", value, phi[i]);
+        }
+
+        // m = 1: J_1' = J_0 - J_1/k, same for H
+        let k: f64 = 2.5;
+        let (single, normal_single) = HelmholtzHypersingular::new(k).maue();
+        let derivative: ArcDerivative<Complex64> = ArcDerivative::new(&nodes);
+        let single: DenseMatrix<Complex64> = corrected(&nodes, single);
+        let normal_single: DenseMatrix<Complex64> = corrected(&nodes, normal_single);
+        let w = SumOp::new(ProductOp::new(&derivative, ProductOp::new(&single, &derivative)), &normal_single);
+        let (h0, h1): (Complex64, Complex64) = hankel1_01(k);
+        let eigenvalue: Complex64 = Complex64::i() * PI * k * k / 2.0 * (h0.re - h1.re / k) * (h0 - h1 / k);
+        let phi: Vec<Complex64> = (0..n).map(|i| Complex64::new(t(i).cos(), t(i).sin())).collect();
+        for (i, value) in w.apply(&phi).iter().enumerate() {
+            assert!((value - eigenvalue * phi[i]).norm() < 1e-7, "{} {}", value, eigenvalue * phi[i]);
+        }
+    }
+}
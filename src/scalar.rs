@@ -0,0 +1,43 @@
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use num_complex::Complex64;
+
+// the number type a kernel hands back -- f64 for Laplace and covariances, Complex64 for Helmholtz
+// everything downstream (blocks, HMatrix, operators) is generic over this so real kernels don't
+// pay for an imaginary part they never use
+pub trait Scalar: Copy + Debug + PartialEq + Send + Sync + 'static
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign + Sum {
+
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_real(x: f64) -> Self;
+    fn conj(self) -> Self;
+    fn abs(self) -> f64; // modulus
+    fn abs_sqr(self) -> f64;
+    fn re(self) -> f64;
+    fn to_complex(self) -> Complex64;
+}
+
+impl Scalar for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn from_real(x: f64) -> Self { x }
+    fn conj(self) -> Self { self }
+    fn abs(self) -> f64 { f64::abs(self) }
+    fn abs_sqr(self) -> f64 { self * self }
+    fn re(self) -> f64 { self }
+    fn to_complex(self) -> Complex64 { Complex64::new(self, 0.0) }
+}
+
+impl Scalar for Complex64 {
+    fn zero() -> Self { Complex64::new(0.0, 0.0) }
+    fn one() -> Self { Complex64::new(1.0, 0.0) }
+    fn from_real(x: f64) -> Self { Complex64::new(x, 0.0) }
+    fn conj(self) -> Self { Complex64::conj(&self) }
+    fn abs(self) -> f64 { self.norm() }
+    fn abs_sqr(self) -> f64 { self.norm_sqr() }
+    fn re(self) -> f64 { self.re }
+    fn to_complex(self) -> Complex64 { self }
+}
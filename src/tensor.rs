@@ -0,0 +1,404 @@
+use num_complex::Complex64;
+use std::f64::consts::PI;
+use crate::kernels::{Kernel, Sum, separation, dot};
+use crate::nodes::Nodes;
+use crate::scalar::Scalar;
+
+// D x D matrix valued kernels -- Stokes flow, elastostatics -- where every node carries D unknowns
+// the HMatrix stays scalar: Tensor<K> presents the kernel entry by entry over unknowns node * D + component, and
+// assembly expands each cluster into block rows / cols of those (see Kernel::components)
+
+pub trait TensorKernel<const D: usize> {
+    type Scalar: Scalar;
+
+    // as Kernel::eval_nodes, [a][b] = component a at target node i due to component b at source node j
+    // double layer kernels pick up normals here
+    fn eval_nodes_tensor(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> [[Self::Scalar; D]; D];
+
+    fn decay_rate(&self) -> f64 { 0.0 }
+    fn oscillation(&self) -> f64 { 0.0 }
+}
+
+// the PointKernel counterpart, for tensors that only need the two positions (Stokeslet, Kelvin, Maxwell)
+pub trait PointTensorKernel<const D: usize>: TensorKernel<D> {
+    // [a][b] = component a at x due to component b at y
+    fn eval_tensor(&self, x: &[f64; D], y: &[f64; D]) -> [[Self::Scalar; D]; D];
+}
+
+// the scalar view HMatrix::assemble works with, eg. HMatrix::assemble(.., Tensor::new(Stokeslet::new(1.0)))
+#[derive(Clone, Copy)]
+pub struct Tensor<K> { pub kernel: K }
+
+impl<K> Tensor<K> {
+    pub fn new(kernel: K) -> Self { Self {kernel}}
+}
+
+impl<const D: usize, K: TensorKernel<D>> Kernel<D> for Tensor<K> {
+    type Scalar = K::Scalar;
+
+    // i and j are unknowns, node * D + component
+    fn eval_nodes(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> K::Scalar {
+        self.kernel.eval_nodes_tensor(targets, i / D, sources, j / D)[i % D][j % D]
+    }
+
+    // assembly hands over whole nodes' worth of unknowns in a row, so work out each D x D tensor once per node pair
+    fn eval_block(&self, targets: &Nodes<D>, rows: &[usize], sources: &Nodes<D>, cols: &[usize], out: &mut [K::Scalar]) {
+        assert_eq!(out.len(), rows.len() * cols.len(), "out must be len(rows) x len(cols)");
+        let n: usize = cols.len();
+        let mut a0: usize = 0;
+        for row_group in rows.chunk_by(|p, q| p / D == q / D) {
+            let mut b0: usize = 0;
+            for col_group in cols.chunk_by(|p, q| p / D == q / D) {
+                let tensor: [[K::Scalar; D]; D] = self.kernel.eval_nodes_tensor(targets, row_group[0] / D, sources, col_group[0] / D);
+                for (a, &i) in row_group.iter().enumerate() {
+                    for (b, &j) in col_group.iter().enumerate() {
+                        out[(a0 + a) * n + b0 + b] = tensor[i % D][j % D];
+                    }
+                }
+                b0 += col_group.len();
+            }
+            a0 += row_group.len();
+        }
+    }
+
+    fn decay_rate(&self) -> f64 { self.kernel.decay_rate() }
+    fn oscillation(&self) -> f64 { self.kernel.oscillation() }
+    fn components(&self) -> usize { D }
+}
+
+// sum of two tensor kernels, eg. Tensor::new(Sum::new(Stokeslet::new(1.0), Stresslet)) for a combined layer
+impl<const D: usize, K1: TensorKernel<D>, K2: TensorKernel<D, Scalar = K1::Scalar>> TensorKernel<D> for Sum<K1, K2> {
+    type Scalar = K1::Scalar;
+
+    fn eval_nodes_tensor(&self, targets: &Nodes<D>, i: usize, sources: &Nodes<D>, j: usize) -> [[K1::Scalar; D]; D] {
+        let mut tensor: [[K1::Scalar; D]; D] = self.first.eval_nodes_tensor(targets, i, sources, j);
+        let second: [[K1::Scalar; D]; D] = self.second.eval_nodes_tensor(targets, i, sources, j);
+        for (row, extra) in tensor.iter_mut().zip(second) {
+            for (value, e) in row.iter_mut().zip(extra) { *value += e; }
+        }
+        tensor
+    }
+
+    fn decay_rate(&self) -> f64 { self.first.decay_rate().min(self.second.decay_rate()) }
+    fn oscillation(&self) -> f64 { self.first.oscillation().max(self.second.oscillation()) }
+}
+
+// diagonal phi(r) delta_ab + dyadic r_a r_b / r^D with phi = -log r in 2D, 1/r in 3D -- the shape Stokeslet and Kelvin share
+// coincident points give 0, the (integrable) self term is left to the caller like the layer kernels
+fn kelvin_form<const D: usize>(x: &[f64; D], y: &[f64; D], diagonal: f64, dyadic: f64) -> [[f64; D]; D] {
+    let mut tensor: [[f64; D]; D] = [[0.0; D]; D];
+    let (diff, r) = separation(x, y);
+    if r == 0.0 { return tensor }
+    let phi: f64 = if D == 2 { -r.ln() } else { 1.0 / r };
+    let scale: f64 = dyadic / r.powi(D as i32);
+    for a in 0..D {
+        for b in 0..D {
+            tensor[a][b] = scale * diff[a] * diff[b];
+        }
+        tensor[a][a] += diagonal * phi;
+    }
+    tensor
+}
+
+// ---------------- STOKES ----------------------
+
+// free space Stokes flow, velocity at x due to a point force at y: 2D (-log r I + r r^T / r^2) / 4 pi mu,
+// 3D (I / r + r r^T / r^3) / 8 pi mu
+#[derive(Clone, Copy)]
+pub struct Stokeslet { pub viscosity: f64 }
+
+impl Stokeslet {
+    pub fn new(viscosity: f64) -> Self {
+        assert!(viscosity > 0.0, "viscosity must be positive");
+        Self {viscosity}
+    }
+}
+
+impl TensorKernel<2> for Stokeslet {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> [[f64; 2]; 2] {
+        self.eval_tensor(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointTensorKernel<2> for Stokeslet {
+    fn eval_tensor(&self, x: &[f64; 2], y: &[f64; 2]) -> [[f64; 2]; 2] {
+        let c: f64 = 1.0 / (4.0 * PI * self.viscosity);
+        kelvin_form(x, y, c, c)
+    }
+}
+
+impl TensorKernel<3> for Stokeslet {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> [[f64; 3]; 3] {
+        self.eval_tensor(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointTensorKernel<3> for Stokeslet {
+    fn eval_tensor(&self, x: &[f64; 3], y: &[f64; 3]) -> [[f64; 3]; 3] {
+        let c: f64 = 1.0 / (8.0 * PI * self.viscosity);
+        kelvin_form(x, y, c, c)
+    }
+}
+
+// Stokes double layer T_abc n_c with r = x - y and the source normal n: 2D -r_a r_b (r.n) / (pi r^4),
+// 3D -3 r_a r_b (r.n) / (4 pi r^5) -- scaled so the integral over a closed surface with outward normals is I inside,
+// 0 outside. needs normals on the source Nodes, doesn't depend on the viscosity
+#[derive(Clone, Copy)]
+pub struct Stresslet;
+
+fn stresslet<const D: usize>(x: &[f64; D], y: &[f64; D], n: &[f64; D], c: f64) -> [[f64; D]; D] {
+    let mut tensor: [[f64; D]; D] = [[0.0; D]; D];
+    let (diff, r) = separation(x, y);
+    if r == 0.0 { return tensor }
+    let scale: f64 = c * dot(&diff, n) / r.powi(D as i32 + 2);
+    for a in 0..D {
+        for b in 0..D {
+            tensor[a][b] = scale * diff[a] * diff[b];
+        }
+    }
+    tensor
+}
+
+impl TensorKernel<2> for Stresslet {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> [[f64; 2]; 2] {
+        let n: &[f64; 2] = sources.normal(j).unwrap_or_else(|| panic!("Stresslet needs normals on the source Nodes"));
+        stresslet(&targets.points[i], &sources.points[j], n, -1.0 / PI)
+    }
+}
+
+impl TensorKernel<3> for Stresslet {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> [[f64; 3]; 3] {
+        let n: &[f64; 3] = sources.normal(j).unwrap_or_else(|| panic!("Stresslet needs normals on the source Nodes"));
+        stresslet(&targets.points[i], &sources.points[j], n, -3.0 / (4.0 * PI))
+    }
+}
+
+// ---------------- ELASTOSTATICS ----------------------
+
+// Kelvin's solution, displacement at x due to a point force at y in an isotropic solid with shear modulus mu and
+// Poisson ratio nu: 2D (plane strain) ((3 - 4nu)(-log r) I + r r^T / r^2) / 8 pi mu (1 - nu),
+// 3D ((3 - 4nu) I / r + r r^T / r^3) / 16 pi mu (1 - nu). nu = 1/2 is incompressible and gives back the Stokeslet
+#[derive(Clone, Copy)]
+pub struct Kelvin { pub shear_modulus: f64, pub poisson_ratio: f64 }
+
+impl Kelvin {
+    pub fn new(shear_modulus: f64, poisson_ratio: f64) -> Self {
+        assert!(shear_modulus > 0.0, "shear modulus must be positive");
+        assert!(poisson_ratio > -1.0 && poisson_ratio <= 0.5, "Poisson ratio must be in (-1, 1/2], got {}", poisson_ratio);
+        Self {shear_modulus, poisson_ratio}
+    }
+}
+
+impl TensorKernel<2> for Kelvin {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<2>, i: usize, sources: &Nodes<2>, j: usize) -> [[f64; 2]; 2] {
+        self.eval_tensor(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointTensorKernel<2> for Kelvin {
+    fn eval_tensor(&self, x: &[f64; 2], y: &[f64; 2]) -> [[f64; 2]; 2] {
+        let c: f64 = 1.0 / (8.0 * PI * self.shear_modulus * (1.0 - self.poisson_ratio));
+        kelvin_form(x, y, (3.0 - 4.0 * self.poisson_ratio) * c, c)
+    }
+}
+
+impl TensorKernel<3> for Kelvin {
+    type Scalar = f64;
+    fn eval_nodes_tensor(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> [[f64; 3]; 3] {
+        self.eval_tensor(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointTensorKernel<3> for Kelvin {
+    fn eval_tensor(&self, x: &[f64; 3], y: &[f64; 3]) -> [[f64; 3]; 3] {
+        let c: f64 = 1.0 / (16.0 * PI * self.shear_modulus * (1.0 - self.poisson_ratio));
+        kelvin_form(x, y, (3.0 - 4.0 * self.poisson_ratio) * c, c)
+    }
+}
+
+// ---------------- MAXWELL ----------------------
+
+// time harmonic electric dyadic Green's function (I + grad grad / k^2) e^{ikr} / (4 pi r), expanded with g = e^{ikr} / (4 pi r)
+// and q = 1 / kr as g [(1 + iq - q^2) I + (3q^2 - 3iq - 1) r^ r^T]. the hypersingular self term isn't integrable so
+// coincident points give 0 and are left to the caller. complex k for lossy media, same conventions as Helmholtz
+#[derive(Clone, Copy)]
+pub struct Maxwell { pub wavenumber: Complex64 }
+
+impl Maxwell {
+    pub fn new(wavenumber: impl Into<Complex64>) -> Self {
+        let wavenumber: Complex64 = wavenumber.into();
+        assert!(wavenumber.norm() > 0.0, "Maxwell needs a non-zero wavenumber, the k -> 0 limit blows up");
+        Self {wavenumber}
+    }
+}
+
+impl TensorKernel<3> for Maxwell {
+    type Scalar = Complex64;
+    fn decay_rate(&self) -> f64 { self.wavenumber.im }
+    fn oscillation(&self) -> f64 { self.wavenumber.re.abs() }
+
+    fn eval_nodes_tensor(&self, targets: &Nodes<3>, i: usize, sources: &Nodes<3>, j: usize) -> [[Complex64; 3]; 3] {
+        self.eval_tensor(&targets.points[i], &sources.points[j])
+    }
+}
+
+impl PointTensorKernel<3> for Maxwell {
+    fn eval_tensor(&self, x: &[f64; 3], y: &[f64; 3]) -> [[Complex64; 3]; 3] {
+        let mut tensor: [[Complex64; 3]; 3] = [[Complex64::new(0.0, 0.0); 3]; 3];
+        let (diff, r) = separation(x, y);
+        if r == 0.0 { return tensor }
+        let ikr: Complex64 = Complex64::i() * self.wavenumber * r;
+        let g: Complex64 = ikr.exp() / (4.0 * PI * r);
+        let q: Complex64 = 1.0 / (self.wavenumber * r);
+        let diagonal: Complex64 = g * (1.0 + Complex64::i() * q - q * q);
+        let dyadic: Complex64 = g * (3.0 * q * q - 3.0 * Complex64::i() * q - 1.0) / (r * r);
+        for a in 0..3 {
+            for b in 0..3 {
+                tensor[a][b] = dyadic * diff[a] * diff[b];
+            }
+            tensor[a][a] += diagonal;
+        }
+        tensor
+    }
+}
+
+#[cfg(test)]
+mod tensor_tests {
+    use super::*;
+    use crate::cluster::ClusterTree;
+    use crate::block::BlockTree;
+    use crate::hmatrix::{HMatrix, ACA_TOL};
+    use crate::operator::LinearOperator;
+    use crate::functions::{approximation_error, dense_matrix};
+
+    #[test]
+    fn stokeslet_is_divergence_free_and_kelvin_limit() {
+        // sum_a d/dx_a G_ab = 0 by central differences
+        let h: f64 = 1e-5;
+        let (x, y): ([f64; 3], [f64; 3]) = ([0.3, -0.2, 0.5], [1.1, 0.4, -0.3]);
+        let stokes: Stokeslet = Stokeslet::new(0.7);
+        for b in 0..3 {
+            let divergence: f64 = (0..3).map(|a| {
+                let (mut xp, mut xm): ([f64; 3], [f64; 3]) = (x, x);
+                xp[a] += h;
+                xm[a] -= h;
+                (stokes.eval_tensor(&xp, &y)[a][b] - stokes.eval_tensor(&xm, &y)[a][b]) / (2.0 * h)
+            }).sum();
+            assert!(divergence.abs() < 1e-8);
+        }
+
+        let kelvin: [[f64; 3]; 3] = Kelvin::new(0.7, 0.5).eval_tensor(&x, &y);
+        let expected: [[f64; 3]; 3] = stokes.eval_tensor(&x, &y);
+        for a in 0..3 { for b in 0..3 { assert!((kelvin[a][b] - expected[a][b]).abs() < 1e-15); } }
+        let kelvin: [[f64; 2]; 2] = Kelvin::new(0.7, 0.5).eval_tensor(&[0.3, -0.2], &[1.1, 0.4]);
+        let expected: [[f64; 2]; 2] = stokes.eval_tensor(&[0.3, -0.2], &[1.1, 0.4]);
+        for a in 0..2 { for b in 0..2 { assert!((kelvin[a][b] - expected[a][b]).abs() < 1e-15); } }
+    }
+
+    #[test]
+    fn stresslet_integrates_to_identity_inside() {
+        // trapezoid rule on the unit circle is spectrally accurate for targets away from it
+        let n: usize = 128;
+        let points: Vec<[f64; 2]> = (0..n).map(|i| {
+            let t: f64 = 2.0 * PI * i as f64 / n as f64;
+            [t.cos(), t.sin()]
+        }).collect();
+        let circle: Nodes<2> = Nodes::with_boundary(points.clone(), vec![2.0 * PI / n as f64; n], points);
+
+        for (target, inside) in [([0.2, 0.1], 1.0), ([-0.4, 0.3], 1.0), ([2.0, 0.5], 0.0)] {
+            let targets: Nodes<2> = Nodes::new(vec![target]);
+            let mut total: [[f64; 2]; 2] = [[0.0; 2]; 2];
+            for j in 0..n {
+                let tensor: [[f64; 2]; 2] = Stresslet.eval_nodes_tensor(&targets, 0, &circle, j);
+                for a in 0..2 { for b in 0..2 { total[a][b] += tensor[a][b] * circle.weight(j); } }
+            }
+            for a in 0..2 {
+                for b in 0..2 {
+                    let expected: f64 = if a == b { inside } else { 0.0 };
+                    assert!((total[a][b] - expected).abs() < 1e-10, "{:?} {:?}", target, total);
+                }
+            }
+        }
+
+        // combined Stokes layer through Sum, entry by entry the two parts added up
+        let combined: Tensor<Sum<Stokeslet, Stresslet>> = Tensor::new(Sum::new(Stokeslet::new(2.0), Stresslet));
+        let targets: Nodes<2> = Nodes::new(vec![[0.2, 0.1]]);
+        for (i, j) in [(0, 6), (1, 6), (1, 13)] {
+            let stokes: f64 = Tensor::new(Stokeslet::new(2.0)).eval_nodes(&targets, i, &circle, j);
+            let stresslet: f64 = Tensor::new(Stresslet).eval_nodes(&targets, i, &circle, j);
+            assert!((combined.eval_nodes(&targets, i, &circle, j) - stokes - stresslet).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn tensor_hmatrix_expands_nodes_into_unknowns() {
+        let points: Vec<[f64; 3]> = (0..150).map(|i| {
+            let t: f64 = 0.1 * i as f64;
+            [t.cos(), t.sin(), 0.2 * t]
+        }).collect();
+        let nodes: Nodes<3> = Nodes::new(points);
+        let tree: ClusterTree<3> = ClusterTree::build_tree(&nodes, 8);
+        let blocks: BlockTree = BlockTree::build_tree(&tree, &tree, 1.0);
+        let kernel: Tensor<Kelvin> = Tensor::new(Kelvin::new(1.0, 0.3));
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, kernel);
+        assert_eq!((hmat.n_rows, hmat.n_cols), (450, 450));
+
+        // unknown 3i + a at node i, component a
+        let tensor: [[f64; 3]; 3] = Kelvin::new(1.0, 0.3).eval_tensor(&nodes.points[7], &nodes.points[120]);
+        for (a, row) in tensor.iter().enumerate() {
+            for (b, entry) in row.iter().enumerate() {
+                assert!((hmat.get(3 * 7 + a, 3 * 120 + b) - entry).abs() < 1e-7 * entry.abs().max(1e-3));
+            }
+        }
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-6);
+
+        let x: Vec<f64> = (0..450).map(|i| (0.37 * i as f64).sin()).collect();
+        let dense: Vec<f64> = dense_matrix(&nodes, &nodes, &hmat.kernel);
+        let exact: Vec<f64> = dense.chunks(450).map(|row| row.iter().zip(&x).map(|(a, b)| a * b).sum()).collect();
+        let y: Vec<f64> = hmat.apply(&x);
+        let err: f64 = y.iter().zip(&exact).map(|(a, b)| (a - b).powi(2)).sum::<f64>().sqrt();
+        assert!(err < 1e-6 * exact.iter().map(|a| a * a).sum::<f64>().sqrt());
+    }
+
+    #[test]
+    fn maxwell_matches_differentiated_helmholtz() {
+        // delta_ab g + d_a d_b g / k^2 with the Hessian of the scalar kernel by central differences
+        use crate::kernels::{Helmholtz, PointKernel};
+        let h: f64 = 1e-4;
+        let (x, y): ([f64; 3], [f64; 3]) = ([0.3, -0.2, 0.5], [1.1, 0.4, -0.3]);
+        for k in [Complex64::new(2.5, 0.0), Complex64::new(1.5, 0.4)] {
+            let scalar: Helmholtz = Helmholtz::new(k);
+            let g = |dx: [f64; 3]| scalar.eval(&[x[0] + dx[0], x[1] + dx[1], x[2] + dx[2]], &y);
+            let tensor: [[Complex64; 3]; 3] = Maxwell::new(k).eval_tensor(&x, &y);
+            for (a, row) in tensor.iter().enumerate() {
+                for (b, entry) in row.iter().enumerate() {
+                    let (mut ea, mut eb): ([f64; 3], [f64; 3]) = ([0.0; 3], [0.0; 3]);
+                    ea[a] = h;
+                    eb[b] = h;
+                    let shift = |sa: f64, sb: f64| [sa * ea[0] + sb * eb[0], sa * ea[1] + sb * eb[1], sa * ea[2] + sb * eb[2]];
+                    let hessian: Complex64 = (g(shift(1.0, 1.0)) - g(shift(1.0, -1.0)) - g(shift(-1.0, 1.0)) + g(shift(-1.0, -1.0))) / (4.0 * h * h);
+                    let delta: f64 = if a == b { 1.0 } else { 0.0 };
+                    let expected: Complex64 = g([0.0; 3]) * delta + hessian / (k * k);
+                    assert!((entry - expected).norm() < 1e-6, "k = {} [{}][{}]: {} vs {}", k, a, b, entry, expected);
+                }
+            }
+        }
+
+        // and it assembles as a 3 x 3 block kernel
+        let nodes: Nodes<3> = Nodes::new((0..120).map(|i| {
+            let t: f64 = 0.1 * i as f64;
+            [t.cos(), t.sin(), 0.15 * t]
+        }).collect());
+        let tree: ClusterTree<3> = ClusterTree::build_tree(&nodes, 8);
+        let kernel: Tensor<Maxwell> = Tensor::new(Maxwell::new(2.0));
+        let blocks: BlockTree = BlockTree::build_tree_for_kernel(&tree, &tree, 1.0, &kernel, ACA_TOL);
+        let hmat = HMatrix::assemble(&nodes, &nodes, &tree, &tree, blocks, kernel);
+        assert_eq!(hmat.n_rows, 360);
+        assert!(approximation_error(&hmat, &nodes, &nodes, 20).frobenius < 1e-6);
+    }
+}
@@ -0,0 +1,140 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::operator::LinearOperator;
+use crate::scalar::Scalar;
+
+// stochastic trace and diagonal estimators, everything goes through matvecs so works for anything LinearOperator
+// Rademacher probes throughout (entries +-1), seed is passed in so estimates are repeatable
+
+fn rademacher<T: Scalar>(rng: &mut StdRng, n: usize) -> Vec<T> {
+    (0..n).map(|_| if rng.r#gen::<bool>() { T::one() } else { -T::one() }).collect()
+}
+
+fn dot<T: Scalar>(a: &[T], b: &[T]) -> T {
+    a.iter().zip(b).map(|(p, &q)| p.conj() * q).sum()
+}
+
+// tr(A) ~ 1/s sum z^H A z, unbiased with variance ~ ||A||_F^2 / s
+pub fn hutchinson_trace<A: LinearOperator>(op: &A, n_samples: usize, seed: u64) -> A::Scalar {
+    assert_eq!(op.nrows(), op.ncols(), "trace needs a square operator");
+    assert!(n_samples > 0);
+
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+    let mut sum: A::Scalar = A::Scalar::zero();
+    for _ in 0..n_samples {
+        let z: Vec<A::Scalar> = rademacher(&mut rng, op.ncols());
+        sum += dot(&z, &op.apply(&z));
+    }
+    sum / A::Scalar::from_real(n_samples as f64)
+}
+
+// Hutch++ (Meyer, Musco, Musco, Woodruff): take the top of the spectrum exactly with a sketch
+// then only run Hutchinson on what's left -- n_matvecs split evenly three ways
+pub fn hutchpp_trace<A: LinearOperator>(op: &A, n_matvecs: usize, seed: u64) -> A::Scalar {
+    assert_eq!(op.nrows(), op.ncols(), "trace needs a square operator");
+    let s: usize = (n_matvecs / 3).max(1);
+    let n: usize = op.ncols();
+
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+
+    // sketch the range of A and orthonormalise
+    let sketch: Vec<Vec<A::Scalar>> = (0..s).map(|_| op.apply(&rademacher(&mut rng, n))).collect();
+    let q: Vec<Vec<A::Scalar>> = orthonormalise(sketch);
+
+    // exact part tr(Q^H A Q)
+    let mut trace: A::Scalar = q.iter().map(|qi| dot(qi, &op.apply(qi))).sum();
+
+    // Hutchinson on (I - QQ^H) A (I - QQ^H)
+    let mut residual: A::Scalar = A::Scalar::zero();
+    for _ in 0..s {
+        let mut g: Vec<A::Scalar> = rademacher(&mut rng, n);
+        project_out(&q, &mut g);
+        residual += dot(&g, &op.apply(&g));
+    }
+    trace += residual / A::Scalar::from_real(s as f64);
+    trace
+}
+
+// diag(A) ~ sum z .* Az / sum z .* z (Bekas, Kokiopoulou, Saad), with Rademacher z the bottom is just n_samples
+pub fn hutchinson_diagonal<A: LinearOperator>(op: &A, n_samples: usize, seed: u64) -> Vec<A::Scalar> {
+    assert_eq!(op.nrows(), op.ncols(), "diagonal needs a square operator");
+    assert!(n_samples > 0);
+
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+    let mut diag: Vec<A::Scalar> = vec![A::Scalar::zero(); op.ncols()];
+    for _ in 0..n_samples {
+        let z: Vec<A::Scalar> = rademacher(&mut rng, op.ncols());
+        for ((d, zi), azi) in diag.iter_mut().zip(&z).zip(op.apply(&z)) { *d += *zi * azi; }
+    }
+    diag.iter_mut().for_each(|d| *d /= A::Scalar::from_real(n_samples as f64));
+    diag
+}
+
+// modified Gram-Schmidt, run twice for stability, columns that vanish are dropped
+fn orthonormalise<T: Scalar>(columns: Vec<Vec<T>>) -> Vec<Vec<T>> {
+    let mut q: Vec<Vec<T>> = Vec::with_capacity(columns.len());
+    for mut col in columns {
+        let norm_before: f64 = dot(&col, &col).re().sqrt();
+        project_out(&q, &mut col);
+        project_out(&q, &mut col);
+        let norm: f64 = dot(&col, &col).re().sqrt();
+        if norm > 1e-12 * norm_before {
+            col.iter_mut().for_each(|c| *c /= T::from_real(norm));
+            q.push(col);
+        }
+    }
+    q
+}
+
+// x <- (I - QQ^H) x
+fn project_out<T: Scalar>(q: &[Vec<T>], x: &mut [T]) {
+    for qi in q {
+        let coeff: T = dot(qi, x);
+        for (xj, qj) in x.iter_mut().zip(qi) { *xj -= coeff * *qj; }
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+    use crate::operator::DenseMatrix;
+    use crate::functions::cardioid_hmatrix;
+    use crate::hmatrix::ACA_TOL;
+
+    // Hilbert like 1 / (1 + i + j), smooth with no dominant diagonal so a few hundred probes pin the estimates down
+    fn smooth_matrix(n: usize) -> DenseMatrix<f64> {
+        let data: Vec<f64> = (0..n * n)
+            .map(|k| 1.0 / (1.0 + (k / n) as f64 + (k % n) as f64))
+            .collect();
+        DenseMatrix::new(n, n, data)
+    }
+
+    #[test]
+    fn exact_diagonal_matches_dense() {
+        let (_, hmat) = cardioid_hmatrix(80, ACA_TOL);
+        let dense: Vec<f64> = hmat.to_dense();
+        for (i, d) in hmat.diagonal().iter().enumerate() {
+            assert!((d - dense[i * 80 + i]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn hutchinson_close_to_trace() {
+        let op: DenseMatrix<f64> = smooth_matrix(60);
+        let exact: f64 = (0..60).map(|i| op.data[i * 60 + i]).sum();
+        let estimate: f64 = hutchinson_trace(&op, 400, 1);
+        assert!((estimate - exact).abs() < 0.1 * exact.abs());
+
+        let diag: Vec<f64> = hutchinson_diagonal(&op, 400, 2);
+        let diag_err: f64 = (0..60).map(|i| (diag[i] - op.data[i * 60 + i]).abs()).fold(0.0, f64::max);
+        assert!(diag_err < 0.5);
+    }
+
+    #[test]
+    fn hutchpp_exact_with_full_sketch() {
+        let (_, hmat) = cardioid_hmatrix(30, ACA_TOL);
+        let exact: f64 = hmat.diagonal().iter().sum();
+        let estimate: f64 = hutchpp_trace(&hmat, 90, 3);
+        assert!((estimate - exact).abs() < 1e-8 * exact.abs());
+    }
+}